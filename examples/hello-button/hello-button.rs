@@ -1,52 +1,40 @@
-#![feature(recover)]
-#![feature(type_ascription)]
-
-#[macro_use] extern crate wui;
+extern crate wui;
 extern crate winapi;
 
-use std::cell::Cell;
 use std::io;
-use std::mem;
-use std::ptr;
 use winapi::*;
 use wui::*;
-use wui::util::TryDrop;
 
 const BTN_HELLO_ID: u16 = 101;
 
-#[derive(Debug)]
-pub struct WndExtra {
-    font: Cell<HFONT>,
+struct HelloButton {
+    // Used by the window and its controls, so it has to live as long as the window does.
+    _font: Font,
 }
 
-impl Default for WndExtra {
-    fn default() -> Self {
-        WndExtra {
-            font: Cell::new(ptr::null_mut()),
+impl WindowHandler for HelloButton {
+    fn on_command(&mut self, wnd: HWND, cmd: WmCommand) -> io::Result<bool> {
+        use wui::WmCommand::*;
+        match cmd {
+            Control { code: BN_CLICKED, id: BTN_HELLO_ID, .. }
+            | Accelerator { id: BTN_HELLO_ID } => {
+                try!(message_box(Some(wnd), "Hello, World!", Some("Hello"), None));
+                Ok(true)
+            },
+            _ => Ok(false)
         }
     }
-}
 
-impl Drop for WndExtra {
-    fn drop(&mut self) {
-        unsafe { self.try_drop_inner().unwrap() }
+    fn on_destroy(&mut self, _: HWND) -> io::Result<()> {
+        MSG::post_quit(0);
+        Ok(())
     }
-}
 
-impl TryDrop for WndExtra {
-    type Err = io::Error;
-
-    unsafe fn try_drop_inner(&mut self) -> Result<(), Self::Err> {
-        let mut r = Ok(());
-        match self.font.get() {
-            v if v.is_null() => (),
-            v => {
-                self.font.set(ptr::null_mut());
-                let font = Font::from_raw(v);
-                r = r.and(font.try_drop());
-            }
+    fn on_mouse_up(&mut self, _: HWND, button: MouseButton, _: INT, _: INT, _: MouseKeys) -> io::Result<bool> {
+        if button == MouseButton::Right {
+            panic!("Kaboom!");
         }
-        r
+        Ok(false)
     }
 }
 
@@ -73,30 +61,23 @@ fn try_main() -> io::Result<()> {
         .icon(try!(Icon::load(None, IDI_APPLICATION)))
         .cursor(try!(Cursor::load(None, IDC_ARROW)))
         .background(try!(Brush::get_sys_color(Color::BtnFace)))
-        .wnd_proc(wnd_proc)
         .register());
 
     // Get a decent default font.
     let msg_font = {
         let ncm = try!(system_parameters_info::get_non_client_metrics());
-        let font = try!(Font::create(&ncm.lfMessageFont));
-        let font_raw = font.as_raw();
-        mem::forget(font);
-        font_raw
+        try!(Font::create(&ncm.lfMessageFont))
     };
-
-    // Build extra window data.
-    let extra = Box::new(WndExtra::default());
-    extra.font.set(msg_font);
-    let extra_ptr = Box::into_raw(extra);
+    let font_raw = msg_font.as_raw();
 
     let wnd = try!(Wnd::new()
         .class_name(&wnd_class)
         .window_name("Hello")
         .style(ws::OverlappedWindow)
         .width(250+30).height(45+40)
-        .param(extra_ptr)
+        .handler(HelloButton { _font: msg_font })
         .create());
+    unsafe { set_font(&wnd, font_raw, false); }
 
     let lbl = try!(Static::new(&wnd)
         .window_name("Click that over there.")
@@ -104,7 +85,7 @@ fn try_main() -> io::Result<()> {
         .x(10).y(10)
         .width(150).height(25)
         .create());
-    unsafe { set_font(&lbl, msg_font, false); }
+    unsafe { set_font(&lbl, font_raw, false); }
 
     let btn = try!(Button::new(&wnd, BTN_HELLO_ID)
         .window_name("Hello")
@@ -112,100 +93,23 @@ fn try_main() -> io::Result<()> {
         .x(170).y(10)
         .width(80).height(25)
         .create());
-    unsafe { set_font(&btn, msg_font, false); }
+    unsafe { set_font(&btn, font_raw, false); }
 
     wnd.show(Show::ShowDefault);
     try!(wnd.update());
 
-    let top_level_wnds = [&wnd];
-
-    loop {
-        let msg = try!(MSG::get(None, None));
-        trace_message(msg.hwnd, msg.message, msg.wParam, msg.lParam);
-        match msg {
-            MSG { message: WM_QUIT, wParam: code, .. } => {
-                ::std::process::exit(code as i32);
-            },
-            mut msg => {
-                let mut processed = false;
-                for wnd in &top_level_wnds {
-                    if msg.is_dialog_message(wnd) {
-                        processed = true;
-                        break;
-                    }
-                }
-                if !processed {
-                    msg.translate();
-                    msg.dispatch();
-                }
-            }
-        }
-    }
-}
-
-fn try_wnd_proc(wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARAM) -> io::Result<LRESULT> {
-    match message {
-        WM_COMMAND => {
-            use wui::WmCommand::*;
-            match wm_command(w_param, l_param) {
-                Control { code: BN_CLICKED, id: BTN_HELLO_ID, .. } => {
-                    try!(message_box(Some(wnd), "Hello, World!", Some("Hello"), None));
-                    Ok(0)
-                },
-                _ => Ok(def_window_proc(wnd, message, w_param, l_param))
-            }
-        },
-        WM_CREATE => {
-            unsafe {
-                let l_param_ptr = l_param as *mut CREATESTRUCTW;
-                if l_param_ptr.is_null() {
-                    return other_error("got null *mut CREATESTRUCT in WM_CREATE");
-                }
-                let l_param = &*l_param_ptr;
-
-                let extra_ptr = l_param.lpCreateParams as *mut WndExtra;
-                if extra_ptr.is_null() {
-                    return other_error("got null *mut WndExtra in WM_CREATE");
-                }
-                let extra = &*extra_ptr;
-
-                let font = extra.font.get();
-                set_font(wnd, font, false);
-
-                try!(set_window_long_ptr(wnd, GWLP_USERDATA, extra_ptr));
-            }
-
-            Ok(0)
-        },
-        WM_DESTROY => {
-            // Drop extra window data.
-            wui_ok_or_warn! {
-                unsafe {
-                    let extra_ptr = try!(set_window_long_ptr(wnd, GWLP_USERDATA, ptr::null::<WndExtra>()));
-
-                    if extra_ptr.is_null () {
-                        return other_error("cannot delete WndExtra: window long was zero");
-                    }
-
-                    /*
-                    This *should* be OK because we just erased the pointer in the window itself.  MSDN doesn't specify whether or not this is atomic, but it *should* be OK.  There's only so un-atomic it can be.
-                    */
-                    let extra_ptr = extra_ptr as *mut _;
-                    let extra = Box::<WndExtra>::from_raw(extra_ptr);
-                    try!(extra.try_drop());
-                    Ok(())
-                }
-            }
-            MSG::post_quit(0);
-            Ok(0)
-        },
-        WM_RBUTTONUP => {
-            panic!("Kaboom!");
-        },
-        message => {
-            Ok(def_window_proc(wnd, message, w_param, l_param))
-        }
-    }
+    let accels = try!(Accelerators::create(&[try!(Accel::parse("Ctrl+H", BTN_HELLO_ID))]));
+    let wnd_raw = wnd.as_raw();
+
+    let code = try!(MessageLoop::new()
+        .filter(|msg| {
+            trace_message(msg.hwnd, msg.message, msg.wParam, msg.lParam);
+            false
+        })
+        .accelerators(&wnd, accels)
+        .filter(move |msg| msg.is_dialog_message(wnd_raw))
+        .run());
+    ::std::process::exit(code);
 }
 
 #[cfg(feature="trace-messages")]
@@ -215,47 +119,3 @@ fn trace_message(wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARAM) {
 
 #[cfg(not(feature="trace-messages"))]
 fn trace_message(_: HWND, _: UINT, _: WPARAM, _: LPARAM) {}
-
-unsafe extern "system" fn wnd_proc(wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
-    match ::std::panic::recover(move || {
-        try_wnd_proc(wnd, message, w_param, l_param)
-    }) {
-        Ok(Ok(res)) => res,
-        Ok(Err(err)) => wui_abort!(
-            "\
-                Unhandled error: {}\r\n\
-                \r\n\
-                wnd: {:p}\r\n\
-                message: {:?}\r\n\
-                w_param: 0x{:x}\r\n\
-                l_param: 0x{:x}\
-            ",
-            err, wnd, FormatMsg(message), w_param, l_param),
-        Err(err) => {
-            let msg = if let Some(err) = err.downcast_ref::<&'static str>() {
-                String::from(*err)
-            } else if let Some(err) = err.downcast_ref::<String>() {
-                (*err).clone()
-            } else if let Some(err) = err.downcast_ref::<io::Error>() {
-                (*err).to_string()
-            } else {
-                String::from("(unknown)")
-            };
-
-            wui_abort!(
-                "\
-                    Panic: {}\r\n\
-                    \r\n\
-                    wnd: {:p}\r\n\
-                    message: {:?}\r\n\
-                    w_param: 0x{:x}\r\n\
-                    l_param: 0x{:x}\
-                ",
-                msg, wnd, FormatMsg(message), w_param, l_param)
-        }
-    }
-}
-
-fn other_error<T>(msg: &str) -> std::io::Result<T> {
-    Err(std::io::Error::new(std::io::ErrorKind::Other, msg))
-}
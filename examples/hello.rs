@@ -22,39 +22,28 @@ fn try_main() -> io::Result<()> {
         .class_name("Hello")
         .instance(try!(get_module_handle(None)))
         .cursor(try!(Cursor::load(None, IDC_ARROW)))
-        .wnd_proc(wnd_proc)
         .register());
 
     let wnd = try!(Wnd::new()
         .class_name(&wnd_class)
         .window_name("Hello")
         .style(wnd_style::OverlappedWindow)
+        .handler(wnd_proc)
         .create());
 
     wnd.show(Show::ShowDefault);
     try!(wnd.update());
 
-    loop {
-        match try!(MSG::get(None, None)) {
-            MSG { message: WM_QUIT, wParam: code, .. } => {
-                ::std::process::exit(code as i32);
-            },
-            msg => {
-                msg.translate();
-                msg.dispatch();
-            }
-        }
-    }
+    let code = try!(MessageLoop::new().run());
+    ::std::process::exit(code);
 }
 
-unsafe extern "system" fn wnd_proc(wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
-    match message {
-        winapi::WM_DESTROY => {
+fn wnd_proc(_: HWND, msg: Msg) -> io::Result<Option<LRESULT>> {
+    match msg {
+        Msg::WmDestroy => {
             MSG::post_quit(0);
-            0
+            Ok(Some(0))
         },
-        message => {
-            def_window_proc(wnd, message, w_param, l_param)
-        }
+        _ => Ok(None)
     }
 }
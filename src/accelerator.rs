@@ -0,0 +1,179 @@
+use std::io;
+use std::ptr;
+use user32;
+use winapi::*;
+use ::last_error;
+use ::resource_id::{ResourceId, ResourceIdThunk, ResourceOrdinal};
+use ::traits::{AsId, IdThunk, AsRaw};
+use ::util::{Shared, TryDrop, WCString};
+use wui_template::{self, FALT, FCONTROL, FSHIFT, FVIRTKEY};
+
+bitflags! {
+    flags AccelModifiers, accel_modifiers: BYTE {
+        const Shift = super::FSHIFT,
+        const Control = super::FCONTROL,
+        const Alt = super::FALT,
+    }
+}
+
+/// A single accelerator: a key combination which sends a `WM_COMMAND`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Accel {
+    pub modifiers: AccelModifiers,
+
+    /// The virtual-key code.
+    pub key: WORD,
+
+    /// The command id; shows up in `WmCommand::Accelerator { id }`.
+    pub id: u16,
+}
+
+impl Accel {
+    /**
+    Parses a key combination such as `"Ctrl+Shift+S"` or `"Alt+F4"`.
+
+    Modifiers are `Ctrl` (or `Control`), `Shift` and `Alt`, and must come before the key.  The key is a letter, a digit, `F1` to `F24`, or one of the names of the other common keys (`Enter`, `Esc`, `Tab`, `Space`, `Backspace`, `Delete`, `Insert`, `Home`, `End`, `PageUp`, `PageDown`, `Left`, `Up`, `Right`, `Down`, `Plus`, `Minus`).  Everything is case-insensitive.
+    */
+    pub fn parse(s: &str, id: u16) -> io::Result<Accel> {
+        let accel = try!(wui_template::Accelerator::parse(s, id));
+        Ok(Accel {
+            modifiers: AccelModifiers::from_bits_truncate(accel.flags),
+            key: accel.key,
+            id: accel.id,
+        })
+    }
+}
+
+/// An accelerator table.
+pub struct Accelerators(HACCEL, Shared);
+
+impl Accelerators {
+    pub fn create(entries: &[Accel]) -> io::Result<Accelerators> {
+        unsafe {
+            let mut accels: Vec<ACCEL> = entries.iter()
+                .map(|e| ACCEL {
+                    fVirt: FVIRTKEY | e.modifiers.bits(),
+                    key: e.key,
+                    cmd: e.id,
+                })
+                .collect();
+            match user32::CreateAcceleratorTableW(accels.as_mut_ptr(), accels.len() as INT) {
+                v if v.is_null() => last_error(),
+                v => Ok(Accelerators(v, Shared::No))
+            }
+        }
+    }
+
+    pub fn load<Name>(instance: Option<HINSTANCE>, table_name: Name) -> io::Result<Accelerators>
+    where Name: AsId<AcceleratorsId> {
+        unsafe {
+            let instance = instance.unwrap_or(ptr::null_mut());
+            let table_name = table_name.into_id_thunk();
+            let table_name = table_name.as_id().as_raw();
+            match user32::LoadAcceleratorsW(instance, table_name) {
+                v if v.is_null() => last_error(),
+                v => Ok(Accelerators(v, Shared::Yes))
+            }
+        }
+    }
+}
+
+impl AsRaw for Accelerators {
+    type Raw = HACCEL;
+
+    fn as_raw(&self) -> Self::Raw {
+        self.0
+    }
+}
+
+impl AsRaw for HACCEL {
+    type Raw = Self;
+
+    fn as_raw(&self) -> Self {
+        *self
+    }
+}
+
+impl Drop for Accelerators {
+    fn drop(&mut self) {
+        unsafe { self.try_drop_inner().unwrap() }
+    }
+}
+
+impl TryDrop for Accelerators {
+    type Err = io::Error;
+
+    unsafe fn try_drop_inner(&mut self) -> Result<(), Self::Err> {
+        // Tables loaded from resources are freed automatically.
+        if let Shared::No = self.1 {
+            match user32::DestroyAcceleratorTable(self.0) {
+                0 => last_error(),
+                _ => Ok(())
+            }
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub struct AcceleratorsId(LPCWSTR);
+
+impl AsRaw for AcceleratorsId {
+    type Raw = LPCWSTR;
+
+    fn as_raw(&self) -> Self::Raw {
+        self.0
+    }
+}
+
+impl<'a> AsId<AcceleratorsId> for &'a str {
+    type IdThunk = WCString;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self.into()
+    }
+}
+
+impl IdThunk<AcceleratorsId> for WCString {
+    fn as_id(&self) -> AcceleratorsId {
+        AcceleratorsId(self.as_ptr())
+    }
+}
+
+impl AsId<AcceleratorsId> for LPCWSTR {
+    type IdThunk = LPCWSTR;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self
+    }
+}
+
+impl IdThunk<AcceleratorsId> for LPCWSTR {
+    fn as_id(&self) -> AcceleratorsId {
+        AcceleratorsId(*self)
+    }
+}
+
+impl AsId<AcceleratorsId> for ResourceOrdinal {
+    type IdThunk = ResourceOrdinal;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self
+    }
+}
+
+impl IdThunk<AcceleratorsId> for ResourceOrdinal {
+    fn as_id(&self) -> AcceleratorsId {
+        AcceleratorsId(self.as_ptr())
+    }
+}
+
+impl<'a> AsId<AcceleratorsId> for &'a ResourceId {
+    type IdThunk = ResourceIdThunk;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self.thunk()
+    }
+}
+
+impl IdThunk<AcceleratorsId> for ResourceIdThunk {
+    fn as_id(&self) -> AcceleratorsId {
+        AcceleratorsId(self.as_ptr())
+    }
+}
@@ -0,0 +1,145 @@
+use std::io;
+use std::mem;
+use std::path::Path;
+use gdi32;
+use winapi::*;
+use ::last_error;
+use ::image::ImageOptions;
+use ::resource_id::{ResourceId, ResourceIdThunk, ResourceOrdinal};
+use ::traits::{AsId, IdThunk, AsRaw, IntoRaw};
+use ::util::{Shared, TryDrop, WCString};
+
+pub struct Bitmap(HBITMAP, Shared);
+
+impl Bitmap {
+    /// Loads a bitmap resource.  The bitmap is deleted when dropped, unless `opts` is shared.
+    pub fn load_image<Name>(instance: Option<HINSTANCE>, bitmap_name: Name, opts: ImageOptions) -> io::Result<Bitmap>
+    where Name: AsId<BitmapId> {
+        unsafe {
+            let bitmap_name = bitmap_name.into_id_thunk();
+            let bitmap_name = bitmap_name.as_id().as_raw();
+            let handle = try!(opts.load_raw(instance, bitmap_name, IMAGE_BITMAP));
+            Ok(Bitmap(handle as HBITMAP, opts.is_shared()))
+        }
+    }
+
+    /// Loads a bitmap from a `.bmp` file.
+    pub fn load_file<P>(path: P, opts: ImageOptions) -> io::Result<Bitmap>
+    where P: AsRef<Path> {
+        unsafe {
+            let handle = try!(opts.load_file_raw(path.as_ref(), IMAGE_BITMAP));
+            Ok(Bitmap(handle as HBITMAP, Shared::No))
+        }
+    }
+}
+
+impl AsRaw for Bitmap {
+    type Raw = HBITMAP;
+    fn as_raw(&self) -> Self::Raw {
+        self.0
+    }
+}
+
+impl AsRaw for HBITMAP {
+    type Raw = Self;
+    fn as_raw(&self) -> Self {
+        *self
+    }
+}
+
+impl Drop for Bitmap {
+    fn drop(&mut self) {
+        unsafe { self.try_drop_inner().unwrap() }
+    }
+}
+
+impl IntoRaw for Bitmap {
+    fn into_raw(self) -> Self::Raw {
+        let r = self.0;
+        mem::forget(self);
+        r
+    }
+}
+
+impl IntoRaw for HBITMAP {
+    fn into_raw(self) -> Self {
+        self
+    }
+}
+
+impl TryDrop for Bitmap {
+    type Err = io::Error;
+
+    unsafe fn try_drop_inner(&mut self) -> Result<(), Self::Err> {
+        if let Shared::No = self.1 {
+            match gdi32::DeleteObject(self.0 as *mut _) {
+                0 => last_error(),
+                _ => Ok(())
+            }
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub struct BitmapId(LPCWSTR);
+
+impl AsRaw for BitmapId {
+    type Raw = LPCWSTR;
+
+    fn as_raw(&self) -> Self::Raw {
+        self.0
+    }
+}
+
+impl<'a> AsId<BitmapId> for &'a str {
+    type IdThunk = WCString;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self.into()
+    }
+}
+
+impl IdThunk<BitmapId> for WCString {
+    fn as_id(&self) -> BitmapId {
+        BitmapId(self.as_ptr())
+    }
+}
+
+impl AsId<BitmapId> for LPCWSTR {
+    type IdThunk = LPCWSTR;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self
+    }
+}
+
+impl IdThunk<BitmapId> for LPCWSTR {
+    fn as_id(&self) -> BitmapId {
+        BitmapId(*self)
+    }
+}
+
+impl AsId<BitmapId> for ResourceOrdinal {
+    type IdThunk = ResourceOrdinal;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self
+    }
+}
+
+impl IdThunk<BitmapId> for ResourceOrdinal {
+    fn as_id(&self) -> BitmapId {
+        BitmapId(self.as_ptr())
+    }
+}
+
+impl<'a> AsId<BitmapId> for &'a ResourceId {
+    type IdThunk = ResourceIdThunk;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self.thunk()
+    }
+}
+
+impl IdThunk<BitmapId> for ResourceIdThunk {
+    fn as_id(&self) -> BitmapId {
+        BitmapId(self.as_ptr())
+    }
+}
@@ -1,9 +1,13 @@
 use std::io;
 use std::mem;
+use std::path::Path;
 use std::ptr;
 use user32;
 use winapi::*;
 use ::last_error;
+use ::dib::create_icon_indirect;
+use ::image::ImageOptions;
+use ::resource_id::{ResourceId, ResourceIdThunk, ResourceOrdinal};
 use ::traits::{AsId, IdThunk, AsRaw, IntoRaw};
 use ::util::{Shared, TryDrop, WCString};
 
@@ -23,6 +27,36 @@ impl Cursor {
             }
         }
     }
+
+    /**
+    Loads a cursor resource with `LoadImageW`, which, unlike `load`, can pick a specific size.
+
+    The cursor is destroyed when dropped, unless `opts` is shared.
+    */
+    pub fn load_image<Name>(instance: Option<HINSTANCE>, cursor_name: Name, opts: ImageOptions) -> io::Result<Cursor>
+    where Name: AsId<CursorId> {
+        unsafe {
+            let cursor_name = cursor_name.into_id_thunk();
+            let cursor_name = cursor_name.as_id().as_raw();
+            let handle = try!(opts.load_raw(instance, cursor_name, IMAGE_CURSOR));
+            Ok(Cursor(handle as HCURSOR, opts.is_shared()))
+        }
+    }
+
+    /// Loads a cursor from a `.cur` or `.ani` file.
+    pub fn load_file<P>(path: P, opts: ImageOptions) -> io::Result<Cursor>
+    where P: AsRef<Path> {
+        unsafe {
+            let handle = try!(opts.load_file_raw(path.as_ref(), IMAGE_CURSOR));
+            Ok(Cursor(handle as HCURSOR, Shared::No))
+        }
+    }
+
+    /// Creates a cursor from `width * height` RGBA pixels, top row first, with the given hotspot.
+    pub fn from_rgba(width: u32, height: u32, rgba: &[u8], hotspot: (u32, u32)) -> io::Result<Cursor> {
+        let cursor = try!(create_icon_indirect(width, height, rgba, Some(hotspot)));
+        Ok(Cursor(cursor as HCURSOR, Shared::No))
+    }
 }
 
 impl AsRaw for Cursor {
@@ -101,3 +135,29 @@ impl IdThunk<CursorId> for LPCWSTR {
         CursorId(*self)
     }
 }
+
+impl AsId<CursorId> for ResourceOrdinal {
+    type IdThunk = ResourceOrdinal;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self
+    }
+}
+
+impl IdThunk<CursorId> for ResourceOrdinal {
+    fn as_id(&self) -> CursorId {
+        CursorId(self.as_ptr())
+    }
+}
+
+impl<'a> AsId<CursorId> for &'a ResourceId {
+    type IdThunk = ResourceIdThunk;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self.thunk()
+    }
+}
+
+impl IdThunk<CursorId> for ResourceIdThunk {
+    fn as_id(&self) -> CursorId {
+        CursorId(self.as_ptr())
+    }
+}
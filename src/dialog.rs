@@ -1,10 +1,17 @@
+use std::cell::{Cell, RefCell};
 use std::io;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
 use user32;
 use winapi::*;
 use wio::wide::ToWide;
 use conv::TryFrom;
 use ::{last_error, other_error};
+use ::dialog_template::DialogTemplate;
+use ::failure::{report_failure, FailureContext, WndProcFailure};
+use ::traits::AsRaw;
+use ::util::TryDrop;
+use ::wnd::{get_window_long_ptr, set_window_long_ptr};
 
 bitflags! {
     flags MessageBoxType, message_box_type: UINT {
@@ -74,3 +81,312 @@ pub fn message_box(wnd: Option<HWND>, text: &str, caption: Option<&str>, type_:
         }
     }
 }
+
+#[cfg(target_pointer_width="32")]
+const DWLP_USER: INT = 8;
+
+#[cfg(target_pointer_width="64")]
+const DWLP_USER: INT = 16;
+
+thread_local! {
+    static MODELESS_DIALOGS: RefCell<Vec<HWND>> = RefCell::new(vec![]);
+}
+
+/**
+A modeless dialog.
+
+The dialog is destroyed when this is dropped, if it hasn't been destroyed already.
+*/
+pub struct Dialog(HWND);
+
+impl Dialog {
+    /**
+    Shows a modal dialog, and returns once it has been closed.
+
+    `dlg_proc` is called for each message sent to the dialog, starting with `WM_INITDIALOG`, and should return `Ok(true)` if it processed the message.  In the case of `WM_INITDIALOG`, returning `Ok(true)` indicates that it has set the keyboard focus itself.
+
+    The result is whatever was passed to `DialogCtx::end_dialog`, or `None` if the dialog was closed some other way.  If `dlg_proc` fails, the dialog is closed and the error returned.  If it panics, the dialog is closed and the panic resumed.
+    */
+    pub fn run_modal<R, F>(instance: Option<HINSTANCE>, parent: Option<HWND>, template: &DialogTemplate, dlg_proc: F) -> io::Result<Option<R>>
+    where F: FnMut(&DialogCtx<R>, UINT, WPARAM, LPARAM) -> io::Result<bool> {
+        unsafe {
+            let instance = instance.unwrap_or(ptr::null_mut());
+            let parent = parent.unwrap_or(ptr::null_mut());
+            let template = aligned_template(template);
+            let mut state = DialogState::new(DialogCtx::new(true), dlg_proc);
+            let state_ptr = &mut state as *mut DialogState<R, F>;
+
+            match user32::DialogBoxIndirectParamW(instance, template.as_ptr() as LPCDLGTEMPLATEW,
+                parent, Some(dialog_proc::<R, F>), state_ptr as LPARAM)
+            {
+                -1 => return last_error(),
+                _ => ()
+            }
+
+            match state.ctx.failure.into_inner() {
+                Some(WndProcFailure::Error(err)) => Err(err),
+                Some(WndProcFailure::Panic(payload)) => panic::resume_unwind(payload),
+                None => Ok(state.ctx.result.into_inner()),
+            }
+        }
+    }
+
+    /**
+    Creates a modeless dialog.
+
+    `dlg_proc` is called as with `run_modal`.  Calling `DialogCtx::end_dialog` destroys the dialog.  Since there is nobody to return them to, errors and panics in `dlg_proc` are reported to the failure policy (see `set_failure_policy`), and the message is left to the default processing.
+
+    Live modeless dialogs are tracked per-thread; use `MsgExt::is_modeless_dialog_message` in the message loop so that keyboard navigation works in all of them.
+    */
+    pub fn create_modeless<F>(instance: Option<HINSTANCE>, parent: Option<HWND>, template: &DialogTemplate, dlg_proc: F) -> io::Result<Dialog>
+    where F: 'static + FnMut(&DialogCtx<()>, UINT, WPARAM, LPARAM) -> io::Result<bool> {
+        unsafe {
+            let instance = instance.unwrap_or(ptr::null_mut());
+            let parent = parent.unwrap_or(ptr::null_mut());
+            let template = aligned_template(template);
+            let state_ptr = Box::into_raw(Box::new(DialogState::new(DialogCtx::<()>::new(false), dlg_proc)));
+
+            // Keep the state alive through creation, even if the dialog is destroyed along the way.
+            (*state_ptr).depth.set(1);
+            let wnd = user32::CreateDialogIndirectParamW(instance, template.as_ptr() as LPCDLGTEMPLATEW,
+                parent, Some(dialog_proc::<(), F>), state_ptr as LPARAM);
+            let result = match wnd {
+                v if v.is_null() => last_error(),
+                _ if (*state_ptr).destroyed.get() => other_error("dialog was destroyed during creation"),
+                v => {
+                    MODELESS_DIALOGS.with(|dlgs| dlgs.borrow_mut().push(v));
+                    Ok(Dialog(v))
+                }
+            };
+            (*state_ptr).depth.set(0);
+            if result.is_err() {
+                drop(Box::from_raw(state_ptr));
+            }
+            result
+        }
+    }
+}
+
+impl AsRaw for Dialog {
+    type Raw = HWND;
+
+    fn as_raw(&self) -> Self::Raw {
+        self.0
+    }
+}
+
+impl Drop for Dialog {
+    fn drop(&mut self) {
+        unsafe { self.try_drop_inner().unwrap() }
+    }
+}
+
+impl TryDrop for Dialog {
+    type Err = io::Error;
+
+    unsafe fn try_drop_inner(&mut self) -> Result<(), Self::Err> {
+        let wnd = self.0;
+        if !MODELESS_DIALOGS.with(|dlgs| dlgs.borrow().contains(&wnd)) {
+            return Ok(());
+        }
+        match user32::DestroyWindow(wnd) {
+            0 => last_error(),
+            _ => Ok(())
+        }
+    }
+}
+
+/**
+Passes `msg` to `IsDialogMessageW` for each live modeless dialog on this thread, stopping at the first one that processes it.
+
+Returns `true` if the message was processed, in which case it should not be translated or dispatched.
+*/
+pub fn is_modeless_dialog_message(msg: &mut MSG) -> bool {
+    use ::msg::MsgExt;
+
+    // Copy the list, since processing the message may create or destroy dialogs.
+    let dlgs = MODELESS_DIALOGS.with(|dlgs| dlgs.borrow().clone());
+    dlgs.into_iter().any(|dlg| msg.is_dialog_message(dlg))
+}
+
+/// The context passed to a dialog procedure.
+pub struct DialogCtx<R> {
+    wnd: Cell<HWND>,
+    modal: bool,
+    closing: Cell<bool>,
+    result: RefCell<Option<R>>,
+    failure: RefCell<Option<WndProcFailure>>,
+}
+
+impl<R> DialogCtx<R> {
+    fn new(modal: bool) -> DialogCtx<R> {
+        DialogCtx {
+            wnd: Cell::new(ptr::null_mut()),
+            modal: modal,
+            closing: Cell::new(false),
+            result: RefCell::new(None),
+            failure: RefCell::new(None),
+        }
+    }
+
+    pub fn wnd(&self) -> HWND {
+        self.wnd.get()
+    }
+
+    /**
+    Closes the dialog, with `result` as the result of `Dialog::run_modal`.
+
+    The dialog is not closed until the dialog procedure returns.  A modeless dialog is then destroyed, so the dialog procedure goes on to get `WM_DESTROY`.
+    */
+    pub fn end_dialog(&self, result: R) -> io::Result<()> {
+        *self.result.borrow_mut() = Some(result);
+        self.close()
+    }
+
+    fn close(&self) -> io::Result<()> {
+        if !self.modal {
+            // Destroying the dialog re-enters the dialog procedure, so leave it to `dialog_proc`.
+            self.closing.set(true);
+            return Ok(());
+        }
+        unsafe {
+            match user32::EndDialog(self.wnd.get(), 0) {
+                0 => last_error(),
+                _ => Ok(())
+            }
+        }
+    }
+
+    /// Returns whether the message should count as handled.
+    fn fail(&self, failure: WndProcFailure, ctx: &FailureContext) -> bool {
+        if !self.modal {
+            report_failure(failure, ctx);
+            return false;
+        }
+        // Keep the first failure; anything after it is likely fallout.
+        let mut slot = self.failure.borrow_mut();
+        if slot.is_none() {
+            *slot = Some(failure);
+        }
+        drop(slot);
+        let _ = self.close();
+        true
+    }
+}
+
+impl<R> AsRaw for DialogCtx<R> {
+    type Raw = HWND;
+
+    fn as_raw(&self) -> Self::Raw {
+        self.wnd.get()
+    }
+}
+
+struct DialogState<R, F> {
+    ctx: DialogCtx<R>,
+    dlg_proc: RefCell<F>,
+
+    /// How many calls to `dialog_proc` for this dialog are running.
+    depth: Cell<usize>,
+
+    /// Set by `WM_NCDESTROY`; the state is freed once `depth` drops to zero.
+    destroyed: Cell<bool>,
+}
+
+impl<R, F> DialogState<R, F> {
+    fn new(ctx: DialogCtx<R>, dlg_proc: F) -> DialogState<R, F> {
+        DialogState {
+            ctx: ctx,
+            dlg_proc: RefCell::new(dlg_proc),
+            depth: Cell::new(0),
+            destroyed: Cell::new(false),
+        }
+    }
+}
+
+unsafe extern "system" fn dialog_proc<R, F>(wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARAM) -> INT_PTR
+where F: FnMut(&DialogCtx<R>, UINT, WPARAM, LPARAM) -> io::Result<bool> {
+    let failure_ctx = FailureContext {
+        wnd: wnd,
+        message: message,
+        w_param: w_param,
+        l_param: l_param,
+        dialog: true,
+    };
+
+    let state_ptr = if message == WM_INITDIALOG {
+        let state_ptr = l_param as *mut DialogState<R, F>;
+        (*state_ptr).ctx.wnd.set(wnd);
+        if let Err(err) = set_window_long_ptr(wnd, DWLP_USER, state_ptr) {
+            (*state_ptr).ctx.fail(WndProcFailure::Error(err), &failure_ctx);
+            return 0;
+        }
+        state_ptr
+    } else {
+        match get_window_long_ptr::<_, DialogState<R, F>>(wnd, DWLP_USER) {
+            Ok(v) => v as *mut DialogState<R, F>,
+            Err(_) => return 0,
+        }
+    };
+
+    // Messages such as `WM_SETFONT` arrive before `WM_INITDIALOG`.
+    if state_ptr.is_null() {
+        return 0;
+    }
+
+    let state = &*state_ptr;
+    state.depth.set(state.depth.get() + 1);
+
+    /*
+    The dialog procedure can end up being re-entered, *e.g.* if it shows a message box.  Leave those messages to the default processing rather than aliasing the closure.
+    */
+    let handled = match state.dlg_proc.try_borrow_mut() {
+        Err(_) => false,
+        Ok(mut dlg_proc) => {
+            let dlg_proc = &mut *dlg_proc;
+            let ctx = &state.ctx;
+            match panic::catch_unwind(AssertUnwindSafe(|| dlg_proc(ctx, message, w_param, l_param))) {
+                Ok(Ok(handled)) => handled,
+                Ok(Err(err)) => ctx.fail(WndProcFailure::Error(err), &failure_ctx),
+                Err(payload) => ctx.fail(WndProcFailure::Panic(payload), &failure_ctx),
+            }
+        }
+    };
+
+    // Now that the closure is free again, it gets the messages sent while destroying the dialog.
+    if state.ctx.closing.get() && state.dlg_proc.try_borrow_mut().is_ok() {
+        state.ctx.closing.set(false);
+        if user32::DestroyWindow(wnd) == 0 {
+            let err = io::Error::last_os_error();
+            report_failure(WndProcFailure::Error(err), &failure_ctx);
+        }
+    }
+
+    if message == WM_NCDESTROY && !state.ctx.modal {
+        MODELESS_DIALOGS.with(|dlgs| dlgs.borrow_mut().retain(|&v| v != wnd));
+        state.destroyed.set(true);
+    }
+
+    /*
+    `WM_NCDESTROY` usually arrives while an outer call is still using the state, since destroying the dialog happens from inside `dialog_proc`.  Whichever call finishes last frees it.
+    */
+    state.depth.set(state.depth.get() - 1);
+    if state.destroyed.get() && state.depth.get() == 0 {
+        drop(Box::from_raw(state_ptr));
+    }
+
+    match message {
+        WM_INITDIALOG => (!handled) as INT_PTR,
+        _ => handled as INT_PTR,
+    }
+}
+
+/// Copies a template into a `DWORD`-aligned buffer, as the dialog functions require.
+fn aligned_template(template: &DialogTemplate) -> Vec<u32> {
+    let bytes = template.to_bytes();
+    let mut buf = vec![0u32; (bytes.len() + 3) / 4];
+    for (i, b) in bytes.into_iter().enumerate() {
+        buf[i / 4] |= (b as u32) << ((i % 4) * 8);
+    }
+    buf
+}
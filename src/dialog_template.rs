@@ -0,0 +1,54 @@
+/*!
+In-memory dialog box templates.
+
+The template model and its serialization come from `wui-template`, which `wui-build` also uses for dialog resources.  Styles are plain `DWORD`s there; use `bits()` to pass in `WndStyle`, `DialogStyle` and the control styles.
+*/
+use std::ops::BitOr;
+use winapi::*;
+use super::wnd::WndStyle;
+
+pub use wui_template::{ControlClass, DialogControl, DialogFont, DialogRect, DialogTemplate};
+
+const DS_ABSALIGN: DWORD = 0x0001;
+const DS_SYSMODAL: DWORD = 0x0002;
+const DS_3DLOOK: DWORD = 0x0004;
+const DS_FIXEDSYS: DWORD = 0x0008;
+const DS_NOFAILCREATE: DWORD = 0x0010;
+const DS_LOCALEDIT: DWORD = 0x0020;
+const DS_SETFONT: DWORD = 0x0040;
+const DS_MODALFRAME: DWORD = 0x0080;
+const DS_NOIDLEMSG: DWORD = 0x0100;
+const DS_SETFOREGROUND: DWORD = 0x0200;
+const DS_CONTROL: DWORD = 0x0400;
+const DS_CENTER: DWORD = 0x0800;
+const DS_CENTERMOUSE: DWORD = 0x1000;
+const DS_CONTEXTHELP: DWORD = 0x2000;
+const DS_SHELLFONT: DWORD = DS_SETFONT | DS_FIXEDSYS;
+
+bitflags! {
+    flags DialogStyle, dialog_style: DWORD {
+        const AbsAlign = super::DS_ABSALIGN,
+        const SysModal = super::DS_SYSMODAL,
+        const ThreeDLook = super::DS_3DLOOK,
+        const FixedSys = super::DS_FIXEDSYS,
+        const NoFailCreate = super::DS_NOFAILCREATE,
+        const LocalEdit = super::DS_LOCALEDIT,
+        const SetFont = super::DS_SETFONT,
+        const ModalFrame = super::DS_MODALFRAME,
+        const NoIdleMsg = super::DS_NOIDLEMSG,
+        const SetForeground = super::DS_SETFOREGROUND,
+        const Control = super::DS_CONTROL,
+        const Center = super::DS_CENTER,
+        const CenterMouse = super::DS_CENTERMOUSE,
+        const ContextHelp = super::DS_CONTEXTHELP,
+        const ShellFont = super::DS_SHELLFONT,
+    }
+}
+
+impl BitOr<DialogStyle> for WndStyle {
+    type Output = WndStyle;
+
+    fn bitor(self, other: DialogStyle) -> WndStyle {
+        self | WndStyle::from_bits(other.bits)
+    }
+}
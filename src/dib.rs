@@ -0,0 +1,159 @@
+/*!
+Helpers for turning RGBA pixels into the bitmaps that make up an icon or cursor.
+*/
+use std::io;
+use std::mem;
+use std::ptr;
+use gdi32;
+use user32;
+use winapi::*;
+use ::last_error;
+
+/**
+Creates an icon (or, given a hotspot, a cursor) from `width * height` RGBA pixels, stored top row first.
+
+Fully transparent pixels are also left out of the AND mask, so the result looks right on displays without alpha blending.
+*/
+pub fn create_icon_indirect(width: u32, height: u32, rgba: &[u8], hotspot: Option<(u32, u32)>) -> io::Result<HICON> {
+    try!(check_rgba(width, height, rgba));
+    if let Some((x, y)) = hotspot {
+        if x >= width || y >= height {
+            return io_err!("hotspot ({}, {}) is outside the {}x{} image", x, y, width, height);
+        }
+    }
+
+    unsafe {
+        let color = try!(create_color_bitmap(width, height, rgba));
+        let mask = gdi32::CreateBitmap(width as INT, height as INT, 1, 1,
+            and_mask(width, height, rgba).as_ptr() as *const _);
+        if mask.is_null() {
+            let err = io::Error::last_os_error();
+            gdi32::DeleteObject(color as *mut _);
+            return Err(err);
+        }
+
+        let (x, y) = hotspot.unwrap_or((width / 2, height / 2));
+        let mut info = ICONINFO {
+            fIcon: if hotspot.is_none() { TRUE } else { FALSE },
+            xHotspot: x,
+            yHotspot: y,
+            hbmMask: mask,
+            hbmColor: color,
+        };
+        let icon = user32::CreateIconIndirect(&mut info);
+        let result = if icon.is_null() { last_error() } else { Ok(icon) };
+
+        // `CreateIconIndirect` copies the bitmaps, so they're ours to delete either way.
+        gdi32::DeleteObject(mask as *mut _);
+        gdi32::DeleteObject(color as *mut _);
+        result
+    }
+}
+
+unsafe fn create_color_bitmap(width: u32, height: u32, rgba: &[u8]) -> io::Result<HBITMAP> {
+    let header = BITMAPINFOHEADER {
+        biSize: mem::size_of::<BITMAPINFOHEADER>() as DWORD,
+        biWidth: width as LONG,
+        // Positive, so the rows are stored bottom-up.
+        biHeight: height as LONG,
+        biPlanes: 1,
+        biBitCount: 32,
+        biCompression: BI_RGB,
+        biSizeImage: 0,
+        biXPelsPerMeter: 0,
+        biYPelsPerMeter: 0,
+        biClrUsed: 0,
+        biClrImportant: 0,
+    };
+    let info = BITMAPINFO {
+        bmiHeader: header,
+        bmiColors: [],
+    };
+
+    let mut bits = ptr::null_mut();
+    let bitmap = gdi32::CreateDIBSection(ptr::null_mut(), &info, DIB_RGB_COLORS, &mut bits, ptr::null_mut(), 0);
+    if bitmap.is_null() || bits.is_null() {
+        return last_error();
+    }
+    let pixels = bgra_bottom_up(width, height, rgba);
+    ptr::copy_nonoverlapping(pixels.as_ptr(), bits as *mut u8, pixels.len());
+    Ok(bitmap)
+}
+
+pub fn check_rgba(width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    if width == 0 || height == 0 {
+        return io_err!("{}x{} image has no pixels", width, height);
+    }
+    let expected = (width as u64) * (height as u64) * 4;
+    if rgba.len() as u64 != expected {
+        return io_err!("{}x{} RGBA image should be {} bytes, not {}", width, height, expected, rgba.len());
+    }
+    Ok(())
+}
+
+/// Converts RGBA pixels, top row first, to premultiplied BGRA pixels, bottom row first, as a 32-bit DIB expects.
+pub fn bgra_bottom_up(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let row_len = width as usize * 4;
+    let mut bgra = Vec::with_capacity(rgba.len());
+    for row in rgba.chunks(row_len).take(height as usize).rev() {
+        for px in row.chunks(4) {
+            let a = px[3];
+            bgra.push(premultiply(px[2], a));
+            bgra.push(premultiply(px[1], a));
+            bgra.push(premultiply(px[0], a));
+            bgra.push(a);
+        }
+    }
+    bgra
+}
+
+fn premultiply(c: u8, a: u8) -> u8 {
+    ((c as u16 * a as u16 + 127) / 255) as u8
+}
+
+/**
+Builds the monochrome AND mask for RGBA pixels: a set bit for each fully transparent pixel.
+
+Rows are top row first and padded to a multiple of 16 bits, as `CreateBitmap` expects.
+*/
+pub fn and_mask(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let stride = ((width as usize + 15) / 16) * 2;
+    let mut mask = vec![0u8; stride * height as usize];
+    for (y, row) in rgba.chunks(width as usize * 4).take(height as usize).enumerate() {
+        for (x, px) in row.chunks(4).enumerate() {
+            if px[3] == 0 {
+                mask[y * stride + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+    }
+    mask
+}
+
+#[cfg(test)]
+#[test]
+fn test_rgba_conversion() {
+    // 2x2: opaque red, half-transparent white; transparent green, opaque blue.
+    let rgba = [
+        0xFF, 0x00, 0x00, 0xFF,   0xFF, 0xFF, 0xFF, 0x80,
+        0x00, 0xFF, 0x00, 0x00,   0x00, 0x00, 0xFF, 0xFF,
+    ];
+
+    assert_eq!(bgra_bottom_up(2, 2, &rgba), vec![
+        0x00, 0x00, 0x00, 0x00,   0xFF, 0x00, 0x00, 0xFF,
+        0x00, 0x00, 0xFF, 0xFF,   0x80, 0x80, 0x80, 0x80,
+    ]);
+    assert_eq!(and_mask(2, 2, &rgba), vec![
+        0x00, 0x00,
+        0x80, 0x00,
+    ]);
+
+    // Rows wider than 16 pixels take more than one word.
+    let mut wide = vec![0xFFu8; 17 * 4];
+    wide[16 * 4 + 3] = 0;
+    assert_eq!(and_mask(17, 1, &wide), vec![0x00, 0x00, 0x80, 0x00]);
+
+    assert!(check_rgba(2, 2, &rgba).is_ok());
+    assert_eq!(check_rgba(2, 3, &rgba).unwrap_err().to_string(),
+        "2x3 RGBA image should be 24 bytes, not 16");
+    assert_eq!(check_rgba(0, 2, &[]).unwrap_err().to_string(), "0x2 image has no pixels");
+}
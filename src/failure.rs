@@ -0,0 +1,173 @@
+/*!
+What happens when a window or dialog procedure fails.
+
+Window procedures are called by Windows, so an error or panic in one can't simply be returned or unwound.  Instead, it is reported to the failure policy, which decides what to do about it.  There is one policy for the whole process, since windows on every thread go through the same procedures.
+*/
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Arc, RwLock};
+use winapi::*;
+use ::{panic_msg, wui_abort};
+use ::debug::FormatMsg;
+use ::wnd_proc::def_window_proc;
+
+/// The policy set with `set_failure_policy`; `None` means `AbortWithDialog`.
+static POLICY: RwLock<Option<Arc<FailurePolicy>>> = RwLock::new(None);
+
+thread_local! {
+    // Failures are kept for the message loop of the thread they happened on.
+    static PENDING: RefCell<Option<WndProcFailure>> = RefCell::new(None);
+}
+
+/// An error returned from, or a panic in, a window or dialog procedure.
+pub enum WndProcFailure {
+    Error(io::Error),
+    Panic(Box<Any + Send>),
+}
+
+impl fmt::Display for WndProcFailure {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WndProcFailure::Error(ref err) => write!(fmt, "Unhandled error: {}", err),
+            WndProcFailure::Panic(ref payload) => write!(fmt, "Panic: {}", panic_msg(payload)),
+        }
+    }
+}
+
+impl fmt::Debug for WndProcFailure {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// The message that was being processed when a procedure failed.
+#[derive(Copy, Clone, Debug)]
+pub struct FailureContext {
+    pub wnd: HWND,
+    pub message: UINT,
+    pub w_param: WPARAM,
+    pub l_param: LPARAM,
+
+    /// Whether the procedure was a dialog procedure, in which case the default processing happens after it returns.
+    pub dialog: bool,
+}
+
+impl FailureContext {
+    /// Passes the message to `DefWindowProcW`, unless it was sent to a dialog.
+    pub fn def_window_proc(&self) -> LRESULT {
+        if self.dialog {
+            0
+        } else {
+            def_window_proc(self.wnd, self.message, self.w_param, self.l_param)
+        }
+    }
+}
+
+impl fmt::Display for FailureContext {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "wnd: {:p}\r\nmessage: {:?}\r\nw_param: 0x{:x}\r\nl_param: 0x{:x}",
+            self.wnd, FormatMsg(self.message), self.w_param, self.l_param)
+    }
+}
+
+pub enum FailurePolicy {
+    /// Shows the failure with `wui_abort`, which exits the process.  This is the default.
+    AbortWithDialog,
+
+    /// Writes the failure to standard error, and carries on as though the message went to `DefWindowProcW`.
+    LogAndContinue,
+
+    /**
+    Keeps the failure for the message loop to pick up with `take_pending_failure`, and carries on as though the message went to `DefWindowProcW`.
+
+    `MessageLoop::run` returns errors and resumes panics kept this way.  Only the first failure is kept; any after it are likely fallout, and are written to standard error.
+    */
+    Propagate,
+
+    /// Calls a hook, whose result is returned from the window procedure.  For dialogs, the result is ignored.  The hook is called on whichever thread the failure happened on.
+    Custom(Box<Fn(WndProcFailure, &FailureContext) -> LRESULT + Send + Sync>),
+}
+
+/// Sets what to do when a window or dialog procedure on any thread fails.
+pub fn set_failure_policy(policy: FailurePolicy) {
+    let mut p = POLICY.write().unwrap_or_else(|e| e.into_inner());
+    *p = Some(Arc::new(policy));
+}
+
+/**
+Reports a failure to the failure policy, returning what the window procedure should return.
+
+Window procedures set up by wui do this themselves; this is for hand-written ones.
+*/
+pub fn report_failure(failure: WndProcFailure, ctx: &FailureContext) -> LRESULT {
+    // Hold on to the policy separately, so the lock isn't held while a hook runs, and in case it changes the policy.
+    let policy = POLICY.read().unwrap_or_else(|e| e.into_inner()).clone();
+    let policy = match policy {
+        Some(policy) => policy,
+        None => Arc::new(FailurePolicy::AbortWithDialog),
+    };
+    match *policy {
+        FailurePolicy::AbortWithDialog => wui_abort(&format!("{}\r\n\r\n{}", failure, ctx), None),
+        FailurePolicy::LogAndContinue => {
+            let _ = writeln!(io::stderr(), "{}\r\n{}", failure, ctx);
+            ctx.def_window_proc()
+        },
+        FailurePolicy::Propagate => {
+            PENDING.with(|pending| {
+                let mut pending = pending.borrow_mut();
+                if pending.is_none() {
+                    *pending = Some(failure);
+                } else {
+                    let _ = writeln!(io::stderr(), "{}\r\n{}", failure, ctx);
+                }
+            });
+            ctx.def_window_proc()
+        },
+        FailurePolicy::Custom(ref hook) => hook(failure, ctx),
+    }
+}
+
+/// Takes the failure kept by `FailurePolicy::Propagate` on the current thread, if any.
+pub fn take_pending_failure() -> Option<WndProcFailure> {
+    PENDING.with(|pending| pending.borrow_mut().take())
+}
+
+#[cfg(test)]
+#[test]
+fn test_failure_policy() {
+    use std::ptr;
+
+    let ctx = FailureContext {
+        wnd: ptr::null_mut(),
+        message: WM_COMMAND,
+        w_param: 0x65,
+        l_param: 0,
+        dialog: true,
+    };
+    let error = |msg: &str| WndProcFailure::Error(io::Error::new(io::ErrorKind::Other, msg));
+
+    // The policy is process-wide, so put the default back even if an assertion fails.
+    struct RestorePolicy;
+    impl Drop for RestorePolicy {
+        fn drop(&mut self) {
+            set_failure_policy(FailurePolicy::AbortWithDialog);
+        }
+    }
+    let _restore = RestorePolicy;
+
+    set_failure_policy(FailurePolicy::Propagate);
+    assert_eq!(report_failure(error("first"), &ctx), 0);
+    assert_eq!(report_failure(WndProcFailure::Panic(Box::new("second")), &ctx), 0);
+    assert_eq!(take_pending_failure().unwrap().to_string(), "Unhandled error: first");
+    assert!(take_pending_failure().is_none());
+
+    set_failure_policy(FailurePolicy::Custom(Box::new(|failure, ctx| {
+        assert_eq!(failure.to_string(), "Panic: Kaboom!");
+        assert_eq!(ctx.message, WM_COMMAND);
+        42
+    })));
+    assert_eq!(report_failure(WndProcFailure::Panic(Box::new("Kaboom!")), &ctx), 42);
+    assert!(take_pending_failure().is_none());
+}
@@ -1,9 +1,13 @@
 use std::io;
 use std::mem;
+use std::path::Path;
 use std::ptr;
 use user32;
 use winapi::*;
 use ::last_error;
+use ::dib::create_icon_indirect;
+use ::image::ImageOptions;
+use ::resource_id::{ResourceId, ResourceIdThunk, ResourceOrdinal};
 use ::traits::{AsId, IdThunk, AsRaw, IntoRaw};
 use ::util::{Shared, TryDrop, WCString};
 
@@ -22,6 +26,36 @@ impl Icon {
             }
         }
     }
+
+    /**
+    Loads an icon resource with `LoadImageW`, which, unlike `load`, can pick a specific size.
+
+    The icon is destroyed when dropped, unless `opts` is shared.
+    */
+    pub fn load_image<Name>(instance: Option<HINSTANCE>, icon_name: Name, opts: ImageOptions) -> io::Result<Icon>
+    where Name: AsId<IconId> {
+        unsafe {
+            let icon_name = icon_name.into_id_thunk();
+            let icon_name = icon_name.as_id().as_raw();
+            let handle = try!(opts.load_raw(instance, icon_name, IMAGE_ICON));
+            Ok(Icon(handle as HICON, opts.is_shared()))
+        }
+    }
+
+    /// Loads an icon from a `.ico` file.
+    pub fn load_file<P>(path: P, opts: ImageOptions) -> io::Result<Icon>
+    where P: AsRef<Path> {
+        unsafe {
+            let handle = try!(opts.load_file_raw(path.as_ref(), IMAGE_ICON));
+            Ok(Icon(handle as HICON, Shared::No))
+        }
+    }
+
+    /// Creates an icon from `width * height` RGBA pixels, top row first.
+    pub fn from_rgba(width: u32, height: u32, rgba: &[u8]) -> io::Result<Icon> {
+        let icon = try!(create_icon_indirect(width, height, rgba, None));
+        Ok(Icon(icon, Shared::No))
+    }
 }
 
 impl AsRaw for Icon {
@@ -108,3 +142,29 @@ impl IdThunk<IconId> for LPCWSTR {
         IconId(*self)
     }
 }
+
+impl AsId<IconId> for ResourceOrdinal {
+    type IdThunk = ResourceOrdinal;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self
+    }
+}
+
+impl IdThunk<IconId> for ResourceOrdinal {
+    fn as_id(&self) -> IconId {
+        IconId(self.as_ptr())
+    }
+}
+
+impl<'a> AsId<IconId> for &'a ResourceId {
+    type IdThunk = ResourceIdThunk;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self.thunk()
+    }
+}
+
+impl IdThunk<IconId> for ResourceIdThunk {
+    fn as_id(&self) -> IconId {
+        IconId(self.as_ptr())
+    }
+}
@@ -0,0 +1,134 @@
+use std::io;
+use std::path::Path;
+use std::ptr;
+use user32;
+use winapi::*;
+use ::last_error;
+use ::util::{Shared, WCString};
+
+bitflags! {
+    flags LoadImageFlags, load_image_flags: UINT {
+        const Monochrome = ::winapi::LR_MONOCHROME,
+        const LoadTransparent = ::winapi::LR_LOADTRANSPARENT,
+        const DefaultSize = ::winapi::LR_DEFAULTSIZE,
+        const VgaColor = ::winapi::LR_VGACOLOR,
+        const LoadMap3dColors = ::winapi::LR_LOADMAP3DCOLORS,
+        const CreateDibSection = ::winapi::LR_CREATEDIBSECTION,
+        const Shared = ::winapi::LR_SHARED,
+    }
+}
+
+/**
+How to load an icon, cursor or bitmap with `LoadImageW`.
+
+By default, images are loaded at their actual size (or, for icons and cursors with several images, the first one), and are owned: they are destroyed when dropped.
+*/
+#[derive(Copy, Clone, Debug)]
+pub struct ImageOptions {
+    size: Option<(INT, INT)>,
+    flags: LoadImageFlags,
+}
+
+impl ImageOptions {
+    pub fn new() -> ImageOptions {
+        ImageOptions {
+            size: None,
+            flags: LoadImageFlags::empty(),
+        }
+    }
+
+    /// Picks the image closest to the given size, stretching it if there isn't an exact match.
+    pub fn size(self, width: INT, height: INT) -> Self {
+        ImageOptions {
+            size: Some((width, height)),
+            ..self
+        }
+    }
+
+    /// Uses the system metrics for icon or cursor size, instead of the image's own size, when no `size` is given.
+    pub fn default_size(self, value: bool) -> Self {
+        self.flag(load_image_flags::DefaultSize, value)
+    }
+
+    /**
+    Shares the image with every other load of the same resource.
+
+    Shared images are never destroyed, so this is only for resources loaded once and kept for the life of the process, such as class icons.  Don't use it for images loaded at a non-standard size: Windows hands back the first copy it cached, whatever size is asked for.  It doesn't apply to files either, so `load_file` ignores it.
+    */
+    pub fn shared(self, value: bool) -> Self {
+        self.flag(load_image_flags::Shared, value)
+    }
+
+    /// Sets any of the other `LR_*` flags.
+    pub fn flags(self, flags: LoadImageFlags) -> Self {
+        ImageOptions {
+            flags: self.flags | flags,
+            ..self
+        }
+    }
+
+    fn flag(mut self, flag: LoadImageFlags, value: bool) -> Self {
+        if value {
+            self.flags.insert(flag);
+        } else {
+            self.flags.remove(flag);
+        }
+        self
+    }
+
+    /// Whether images loaded with these options are shared, and so must not be destroyed.
+    pub fn is_shared(&self) -> Shared {
+        if self.flags.contains(load_image_flags::Shared) { Shared::Yes } else { Shared::No }
+    }
+
+    /**
+    Calls `LoadImageW` with these options.
+
+    `image_type` is one of `IMAGE_BITMAP`, `IMAGE_ICON` or `IMAGE_CURSOR`.  Prefer `Icon::load_image`, `Cursor::load_image` or `Bitmap::load_image`, which take care of destroying the handle.
+    */
+    pub unsafe fn load_raw(&self, instance: Option<HINSTANCE>, name: LPCWSTR, image_type: UINT) -> io::Result<HANDLE> {
+        let instance = instance.unwrap_or(ptr::null_mut());
+        let (cx, cy, flags) = self.to_raw();
+        match user32::LoadImageW(instance, name, image_type, cx, cy, flags) {
+            v if v.is_null() => last_error(),
+            v => Ok(v)
+        }
+    }
+
+    /**
+    Calls `LoadImageW` with these options and `LR_LOADFROMFILE`.
+
+    Images loaded from files are never shared, so `shared` is ignored and the handle always needs destroying.
+    */
+    pub unsafe fn load_file_raw(&self, path: &Path, image_type: UINT) -> io::Result<HANDLE> {
+        let path = WCString::from(path);
+        let (cx, cy, flags) = self.to_raw();
+        let flags = (flags & !LR_SHARED) | LR_LOADFROMFILE;
+        match user32::LoadImageW(ptr::null_mut(), path.as_ptr(), image_type, cx, cy, flags) {
+            v if v.is_null() => last_error(),
+            v => Ok(v)
+        }
+    }
+
+    fn to_raw(&self) -> (INT, INT, UINT) {
+        let (cx, cy) = self.size.unwrap_or((0, 0));
+        (cx, cy, self.flags.bits())
+    }
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions::new()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_image_options() {
+    assert_eq!(ImageOptions::new().to_raw(), (0, 0, 0));
+    assert_eq!(ImageOptions::new().size(32, 16).shared(true).to_raw(), (32, 16, LR_SHARED));
+    assert_eq!(ImageOptions::new().default_size(true).shared(true).shared(false).to_raw(),
+        (0, 0, LR_DEFAULTSIZE));
+    assert_eq!(ImageOptions::new().flags(load_image_flags::Monochrome).default_size(true).to_raw(),
+        (0, 0, LR_MONOCHROME | LR_DEFAULTSIZE));
+}
@@ -5,6 +5,7 @@ extern crate kernel32;
 extern crate user32;
 extern crate winapi;
 extern crate wio;
+extern crate wui_template;
 
 #[macro_use] mod macros;
 #[macro_use] mod bitflags;
@@ -15,6 +16,8 @@ pub mod ext {
 
 pub mod util;
 
+#[doc(inline)] pub use accelerator::*;
+#[doc(inline)] pub use bitmap::*;
 #[doc(inline)] pub use brush::*;
 #[doc(inline)] pub use button::*;
 #[doc(inline)] pub use config::*;
@@ -22,18 +25,25 @@ pub mod util;
 #[doc(inline)] pub use dc::*;
 #[doc(inline)] pub use debug::*;
 #[doc(inline)] pub use dialog::*;
+#[doc(inline)] pub use dialog_template::*;
 #[doc(inline)] pub use dll::*;
+#[doc(inline)] pub use failure::*;
 #[doc(inline)] pub use icon::*;
+#[doc(inline)] pub use image::*;
 #[doc(inline)] pub use menu::*;
 #[doc(inline)] pub use msg::*;
 #[doc(inline)] pub use paint::*;
+#[doc(inline)] pub use resource_id::*;
 #[doc(inline)] pub use static_::*;
+#[doc(inline)] pub use string_table::*;
 #[doc(inline)] pub use text::*;
 #[doc(inline)] pub use traits::*;
 #[doc(inline)] pub use wnd::*;
 #[doc(inline)] pub use wnd_class::*;
 #[doc(inline)] pub use wnd_proc::*;
 
+mod accelerator;
+mod bitmap;
 mod brush;
 mod button;
 mod config;
@@ -41,12 +51,18 @@ mod cursor;
 mod dc;
 mod debug;
 mod dialog;
+mod dialog_template;
+mod dib;
 mod dll;
+mod failure;
 mod icon;
+mod image;
 mod menu;
 mod msg;
 mod paint;
+mod resource_id;
 mod static_;
+mod string_table;
 mod text;
 mod traits;
 mod wnd;
@@ -79,3 +95,13 @@ fn last_error<T>() -> std::io::Result<T> {
 fn other_error<T>(msg: &str) -> std::io::Result<T> {
     Err(std::io::Error::new(std::io::ErrorKind::Other, msg))
 }
+
+fn panic_msg(payload: &Box<std::any::Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&'static str>() {
+        String::from(*msg)
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        String::from("(unknown)")
+    }
+}
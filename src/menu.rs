@@ -1,5 +1,38 @@
+use std::io;
+use std::mem;
+use std::ptr;
+use user32;
 use winapi::*;
-use ::traits::AsRaw;
+use ::last_error;
+use wui_template::{self, menu_template_data, MFS_CHECKED, MFS_DEFAULT, MFS_DISABLED, MFT_RADIOCHECK};
+use ::traits::{AsRaw, IntoRaw};
+use ::util::{TryDrop, WCString};
+
+extern "system" {
+    fn SetMenuDefaultItem(hMenu: HMENU, uItem: UINT, fByPos: UINT) -> BOOL;
+}
+
+/**
+An owned menu.
+
+The menu (and all of its sub-menus) is destroyed when this is dropped.  Passing it to `WndBuilder::menu` hands it over to the window instead.
+*/
+pub struct Menu(HMENU);
+
+impl Menu {
+    pub fn new() -> MenuBuilder {
+        MenuBuilder {
+            entries: vec![],
+        }
+    }
+}
+
+impl AsRaw for Menu {
+    type Raw = HMENU;
+    fn as_raw(&self) -> Self::Raw {
+        self.0
+    }
+}
 
 impl AsRaw for HMENU {
     type Raw = Self;
@@ -7,3 +40,242 @@ impl AsRaw for HMENU {
         *self
     }
 }
+
+impl IntoRaw for Menu {
+    fn into_raw(self) -> Self::Raw {
+        let r = self.0;
+        mem::forget(self);
+        r
+    }
+}
+
+impl IntoRaw for HMENU {
+    fn into_raw(self) -> Self {
+        self
+    }
+}
+
+impl Drop for Menu {
+    fn drop(&mut self) {
+        unsafe { self.try_drop_inner().unwrap() }
+    }
+}
+
+impl TryDrop for Menu {
+    type Err = io::Error;
+
+    unsafe fn try_drop_inner(&mut self) -> Result<(), Self::Err> {
+        match user32::DestroyMenu(self.0) {
+            0 => last_error(),
+            _ => Ok(())
+        }
+    }
+}
+
+/**
+Describes the contents of a menu.
+
+The ids given to items are what show up in `WmCommand::Menu { id }` when an item is chosen.
+*/
+pub struct MenuBuilder {
+    entries: Vec<MenuEntry>,
+}
+
+enum MenuEntry {
+    Item(MenuItem),
+    Separator,
+    SubMenu(String, MenuBuilder),
+}
+
+impl MenuBuilder {
+    pub fn item(mut self, item: MenuItem) -> Self {
+        self.entries.push(MenuEntry::Item(item));
+        self
+    }
+
+    pub fn separator(mut self) -> Self {
+        self.entries.push(MenuEntry::Separator);
+        self
+    }
+
+    pub fn sub_menu(mut self, text: &str, sub_menu: MenuBuilder) -> Self {
+        self.entries.push(MenuEntry::SubMenu(text.into(), sub_menu));
+        self
+    }
+
+    /// Creates the menu as a menu bar, suitable for `WndBuilder::menu`.
+    pub fn create(&self) -> io::Result<Menu> {
+        unsafe {
+            match user32::CreateMenu() {
+                v if v.is_null() => last_error(),
+                v => {
+                    let menu = Menu(v);
+                    try!(self.append_to(&menu));
+                    Ok(menu)
+                }
+            }
+        }
+    }
+
+    /// Creates the menu as a pop-up menu, suitable for `TrackPopupMenu`.
+    pub fn create_popup(&self) -> io::Result<Menu> {
+        unsafe {
+            match user32::CreatePopupMenu() {
+                v if v.is_null() => last_error(),
+                v => {
+                    let menu = Menu(v);
+                    try!(self.append_to(&menu));
+                    Ok(menu)
+                }
+            }
+        }
+    }
+
+    fn append_to(&self, menu: &Menu) -> io::Result<()> {
+        unsafe {
+            let menu = menu.as_raw();
+            for entry in &self.entries {
+                let ok = match *entry {
+                    MenuEntry::Item(ref item) => {
+                        let mut flags = MF_STRING;
+                        if item.checked { flags |= MF_CHECKED; }
+                        if !item.enabled { flags |= MF_GRAYED; }
+                        if item.radio { flags |= MFT_RADIOCHECK; }
+                        let text = WCString::from(&item.text[..]);
+                        let ok = user32::AppendMenuW(menu, flags, item.id as UINT_PTR, text.as_ptr());
+                        if ok != 0 && item.default {
+                            SetMenuDefaultItem(menu, item.id as UINT, FALSE as UINT)
+                        } else {
+                            ok
+                        }
+                    },
+                    MenuEntry::Separator => {
+                        user32::AppendMenuW(menu, MF_SEPARATOR, 0, ptr::null())
+                    },
+                    MenuEntry::SubMenu(ref text, ref sub_menu) => {
+                        let sub_menu = try!(sub_menu.create_popup());
+                        let text = WCString::from(&text[..]);
+                        match user32::AppendMenuW(menu, MF_POPUP | MF_STRING,
+                            sub_menu.as_raw() as UINT_PTR, text.as_ptr())
+                        {
+                            0 => 0,
+                            ok => {
+                                // The sub-menu now belongs to `menu`.
+                                sub_menu.into_raw();
+                                ok
+                            }
+                        }
+                    },
+                };
+                if ok == 0 {
+                    return last_error();
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Serializes the menu to the `MENUEX` template layout, as used by `RT_MENU` resources and `LoadMenuIndirectW`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        menu_template_data(&self.template_items())
+    }
+
+    fn template_items(&self) -> Vec<wui_template::MenuItem> {
+        self.entries.iter().map(|entry| match *entry {
+            MenuEntry::Item(ref item) => {
+                let mut state = 0;
+                if item.checked { state |= MFS_CHECKED; }
+                if !item.enabled { state |= MFS_DISABLED; }
+                if item.default { state |= MFS_DEFAULT; }
+                wui_template::MenuItem {
+                    text: item.text.clone(),
+                    id: item.id as u32,
+                    item_type: if item.radio { MFT_RADIOCHECK } else { 0 },
+                    state: state,
+                    sub_menu: None,
+                }
+            },
+            MenuEntry::Separator => wui_template::MenuItem::separator(),
+            MenuEntry::SubMenu(ref text, ref sub_menu) => wui_template::MenuItem {
+                text: text.clone(),
+                id: 0,
+                item_type: 0,
+                state: 0,
+                sub_menu: Some(sub_menu.template_items()),
+            },
+        }).collect()
+    }
+}
+
+/// A command item in a menu.
+pub struct MenuItem {
+    id: u16,
+    text: String,
+    checked: bool,
+    radio: bool,
+    default: bool,
+    enabled: bool,
+}
+
+impl MenuItem {
+    pub fn new(id: u16, text: &str) -> MenuItem {
+        MenuItem {
+            id: id,
+            text: text.into(),
+            checked: false,
+            radio: false,
+            default: false,
+            enabled: true,
+        }
+    }
+
+    pub fn checked(self, value: bool) -> Self {
+        MenuItem {
+            checked: value,
+            ..self
+        }
+    }
+
+    /// Shows the check mark as a radio button bullet.
+    pub fn radio(self, value: bool) -> Self {
+        MenuItem {
+            radio: value,
+            ..self
+        }
+    }
+
+    /// Makes this the default item, shown in bold.  A menu should have at most one.
+    pub fn default(self, value: bool) -> Self {
+        MenuItem {
+            default: value,
+            ..self
+        }
+    }
+
+    pub fn enabled(self, value: bool) -> Self {
+        MenuItem {
+            enabled: value,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_menu_to_bytes() {
+    use wui_template::MFT_SEPARATOR;
+
+    let menu = Menu::new()
+        .sub_menu("F", Menu::new()
+            .item(MenuItem::new(100, "O").default(true))
+            .separator())
+        .item(MenuItem::new(300, "Hi").radio(true).checked(true).enabled(false));
+
+    let items = menu.template_items();
+    assert_eq!(items.len(), 2);
+    let sub_menu = items[0].sub_menu.as_ref().unwrap();
+    assert_eq!((&sub_menu[0].text[..], sub_menu[0].id, sub_menu[0].state), ("O", 100, MFS_DEFAULT));
+    assert_eq!(sub_menu[1].item_type, MFT_SEPARATOR);
+    assert_eq!((items[1].item_type, items[1].state), (MFT_RADIOCHECK, MFS_CHECKED | MFS_DISABLED));
+    assert_eq!(menu.to_bytes(), menu_template_data(&items));
+}
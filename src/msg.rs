@@ -1,19 +1,42 @@
 use std::io;
 use std::mem;
+use std::panic;
 use std::ptr;
 use user32;
 use winapi::*;
 use ::last_error;
+use ::failure::{take_pending_failure, WndProcFailure};
 use ::traits::AsRaw;
 use ::util::WCString;
+use super::accelerator::Accelerators;
 use super::wnd::Wnd;
 
+extern "system" {
+    fn TranslateAcceleratorW(hWnd: HWND, hAccTable: HACCEL, lpMsg: LPMSG) -> INT;
+}
+
+bitflags! {
+    flags PeekMessageFlags, peek_message_flags: UINT {
+        const Remove = ::winapi::PM_REMOVE,
+        const NoYield = ::winapi::PM_NOYIELD,
+        const QsInput = ::winapi::PM_QS_INPUT,
+        const QsPostMessage = ::winapi::PM_QS_POSTMESSAGE,
+        const QsPaint = ::winapi::PM_QS_PAINT,
+        const QsSendMessage = ::winapi::PM_QS_SENDMESSAGE,
+    }
+}
+
 pub trait MsgExt: Sized {
     fn get(wnd: Option<&Wnd>, msg_filter: Option<(UINT, UINT)>) -> io::Result<Self>;
+
+    /// Returns the next message, if there is one, without waiting.  Without `Remove`, the message is left in the queue.
+    fn peek(wnd: Option<&Wnd>, msg_filter: Option<(UINT, UINT)>, flags: PeekMessageFlags) -> Option<Self>;
     fn post_quit(exit_code: INT);
 
     fn dispatch(&self) -> LRESULT;
     fn is_dialog_message<Wnd>(&mut self, dlg: Wnd) -> bool where Wnd: AsRaw<Raw=HWND>;
+    fn is_modeless_dialog_message(&mut self) -> bool;
+    fn translate_accelerator<Wnd>(&mut self, wnd: Wnd, accel: &Accelerators) -> bool where Wnd: AsRaw<Raw=HWND>;
     fn translate(&self) -> bool;
 }
 
@@ -30,6 +53,18 @@ impl MsgExt for MSG {
         }
     }
 
+    fn peek(wnd: Option<&Wnd>, msg_filter: Option<(UINT, UINT)>, flags: PeekMessageFlags) -> Option<MSG> {
+        unsafe {
+            let mut msg = mem::zeroed();
+            let wnd = wnd.map(|v| v.as_raw()).unwrap_or(ptr::null_mut());
+            let (msg_filter_min, msg_filter_max) = msg_filter.unwrap_or((0, 0));
+            match user32::PeekMessageW(&mut msg, wnd, msg_filter_min, msg_filter_max, flags.bits()) {
+                0 => None,
+                _ => Some(msg)
+            }
+        }
+    }
+
     fn post_quit(exit_code: INT) {
         unsafe {
             user32::PostQuitMessage(exit_code)
@@ -53,6 +88,26 @@ impl MsgExt for MSG {
         }
     }
 
+    fn is_modeless_dialog_message(&mut self) -> bool {
+        ::dialog::is_modeless_dialog_message(self)
+    }
+
+    /**
+    Turns key presses matching `accel` into `WM_COMMAND` messages sent to `wnd`.
+
+    Returns `true` if the message was translated, in which case it should not be processed any further.  This should be called before `is_dialog_message` and `translate`.
+    */
+    fn translate_accelerator<Wnd>(&mut self, wnd: Wnd, accel: &Accelerators) -> bool
+    where Wnd: AsRaw<Raw=HWND> {
+        unsafe {
+            let wnd = wnd.as_raw();
+            match TranslateAcceleratorW(wnd, accel.as_raw(), self) {
+                0 => false,
+                _ => true
+            }
+        }
+    }
+
     fn translate(&self) -> bool {
         unsafe {
             match user32::TranslateMessage(self) {
@@ -73,3 +128,124 @@ pub fn register_window_message(string: &str) -> io::Result<UINT> {
         }
     }
 }
+
+/// What to do once the idle callback passed to `MessageLoop::run_with_idle` returns.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IdleResult {
+    /// Call the callback again as soon as the queue is empty.
+    Continue,
+
+    /// Wait for the next message.
+    Wait,
+}
+
+/**
+A message loop: gets messages, and translates and dispatches them until `WM_QUIT`.
+
+Filters get each message first, in the order they were added, and return `true` if they processed it, in which case it goes no further.
+
+If a window or dialog procedure fails under `FailurePolicy::Propagate`, the loop stops after dispatching the message; errors are returned, and panics resumed.
+*/
+pub struct MessageLoop {
+    filters: Vec<Box<FnMut(&mut MSG) -> bool>>,
+}
+
+impl MessageLoop {
+    pub fn new() -> MessageLoop {
+        MessageLoop {
+            filters: vec![],
+        }
+    }
+
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where F: 'static + FnMut(&mut MSG) -> bool {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Translates key presses matching `accels` into `WM_COMMAND` messages for `wnd`.  This should come before `modeless_dialogs`.
+    pub fn accelerators<Wnd>(self, wnd: Wnd, accels: Accelerators) -> Self
+    where Wnd: AsRaw<Raw=HWND> {
+        let wnd = wnd.as_raw();
+        self.filter(move |msg| msg.translate_accelerator(wnd, &accels))
+    }
+
+    /// Gives live modeless dialogs their keyboard navigation.
+    pub fn modeless_dialogs(self) -> Self {
+        self.filter(|msg| msg.is_modeless_dialog_message())
+    }
+
+    /// Runs until `WM_QUIT`, returning its exit code.
+    pub fn run(&mut self) -> io::Result<INT> {
+        loop {
+            let msg = try!(MSG::get(None, None));
+            if msg.message == WM_QUIT {
+                return Ok(msg.wParam as INT);
+            }
+            try!(self.process(msg));
+        }
+    }
+
+    /// Runs until `WM_QUIT`, returning its exit code, and calls `idle` whenever the queue is empty.
+    pub fn run_with_idle<F>(&mut self, mut idle: F) -> io::Result<INT>
+    where F: FnMut() -> IdleResult {
+        loop {
+            if let Some(code) = try!(self.pump()) {
+                return Ok(code);
+            }
+            if idle() == IdleResult::Wait {
+                try!(wait_message());
+            }
+        }
+    }
+
+    /**
+    Processes every message already in the queue, without waiting for more.
+
+    Returns the exit code if `WM_QUIT` was reached.  The quit message is posted again, so that an enclosing loop sees it too; this is for processing messages during a long operation, or in a nested loop.
+    */
+    pub fn pump_pending(&mut self) -> io::Result<Option<INT>> {
+        let code = try!(self.pump());
+        if let Some(code) = code {
+            MSG::post_quit(code);
+        }
+        Ok(code)
+    }
+
+    fn pump(&mut self) -> io::Result<Option<INT>> {
+        while let Some(msg) = MSG::peek(None, None, peek_message_flags::Remove) {
+            if msg.message == WM_QUIT {
+                return Ok(Some(msg.wParam as INT));
+            }
+            try!(self.process(msg));
+        }
+        Ok(None)
+    }
+
+    fn process(&mut self, mut msg: MSG) -> io::Result<()> {
+        if !self.filters.iter_mut().any(|filter| filter(&mut msg)) {
+            msg.translate();
+            msg.dispatch();
+        }
+        match take_pending_failure() {
+            None => Ok(()),
+            Some(WndProcFailure::Error(err)) => Err(err),
+            Some(WndProcFailure::Panic(payload)) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl Default for MessageLoop {
+    fn default() -> Self {
+        MessageLoop::new()
+    }
+}
+
+fn wait_message() -> io::Result<()> {
+    unsafe {
+        match user32::WaitMessage() {
+            0 => last_error(),
+            _ => Ok(())
+        }
+    }
+}
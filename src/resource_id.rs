@@ -0,0 +1,95 @@
+use winapi::*;
+use ::util::WCString;
+
+/**
+A numbered resource, as produced by `MAKEINTRESOURCE`.
+
+This can be used anywhere a resource name is accepted, such as `Icon::load`.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ResourceOrdinal(pub u16);
+
+impl ResourceOrdinal {
+    /// Recovers the ordinal from a pointer such as `IDI_APPLICATION`.  Returns `None` if the pointer is a real string.
+    pub fn from_ptr(ptr: LPCWSTR) -> Option<ResourceOrdinal> {
+        match ptr as usize {
+            v if v >> 16 == 0 => Some(ResourceOrdinal(v as u16)),
+            _ => None,
+        }
+    }
+
+    pub fn as_ptr(&self) -> LPCWSTR {
+        self.0 as usize as LPCWSTR
+    }
+}
+
+/// A resource identified either by name or by number.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ResourceId {
+    Name(String),
+    Ordinal(u16),
+}
+
+impl ResourceId {
+    /// Converts the id into something that can be passed to the Win32 API.
+    pub fn thunk(&self) -> ResourceIdThunk {
+        match *self {
+            ResourceId::Name(ref name) => ResourceIdThunk::Name(WCString::from(&name[..])),
+            ResourceId::Ordinal(id) => ResourceIdThunk::Ordinal(ResourceOrdinal(id)),
+        }
+    }
+}
+
+impl From<u16> for ResourceId {
+    fn from(v: u16) -> Self {
+        ResourceId::Ordinal(v)
+    }
+}
+
+impl From<ResourceOrdinal> for ResourceId {
+    fn from(v: ResourceOrdinal) -> Self {
+        ResourceId::Ordinal(v.0)
+    }
+}
+
+impl<'a> From<&'a str> for ResourceId {
+    fn from(v: &'a str) -> Self {
+        ResourceId::Name(v.into())
+    }
+}
+
+impl From<String> for ResourceId {
+    fn from(v: String) -> Self {
+        ResourceId::Name(v)
+    }
+}
+
+/// A `ResourceId` in the form the Win32 API wants; this keeps a name's wide string alive for as long as the pointer is needed.
+pub enum ResourceIdThunk {
+    Name(WCString),
+    Ordinal(ResourceOrdinal),
+}
+
+impl ResourceIdThunk {
+    pub fn as_ptr(&self) -> LPCWSTR {
+        match *self {
+            ResourceIdThunk::Name(ref name) => name.as_ptr(),
+            ResourceIdThunk::Ordinal(ord) => ord.as_ptr(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_resource_ordinal() {
+    assert_eq!(ResourceOrdinal::from_ptr(IDI_APPLICATION), Some(ResourceOrdinal(32512)));
+    assert_eq!(ResourceOrdinal(32512).as_ptr(), IDI_APPLICATION);
+
+    let name = WCString::from("APP");
+    assert_eq!(ResourceOrdinal::from_ptr(name.as_ptr()), None);
+
+    let thunk = ResourceId::from("APP").thunk();
+    assert_eq!(ResourceOrdinal::from_ptr(thunk.as_ptr()), None);
+    let thunk = ResourceId::from(7).thunk();
+    assert_eq!(ResourceOrdinal::from_ptr(thunk.as_ptr()), Some(ResourceOrdinal(7)));
+}
@@ -0,0 +1,74 @@
+use std::io;
+use std::ptr;
+use std::slice;
+use kernel32;
+use winapi::*;
+use ::{last_error, other_error};
+use ::resource_id::ResourceOrdinal;
+
+const RT_STRING: u16 = 6;
+
+/**
+Loads a string from the `STRINGTABLE` resources of a module.
+
+Uses the string table matching the current thread's UI language, falling back on whatever the resource loader picks if there isn't one.  A string which isn't in the table is an error, even if others near it are.
+*/
+pub fn load_string(instance: Option<HINSTANCE>, id: u16) -> io::Result<String> {
+    unsafe {
+        let instance = instance.unwrap_or(ptr::null_mut());
+
+        // Strings are stored in bundles of sixteen, and `LoadStringW` can't tell a missing string from an empty one in a bundle that exists, so look in the bundle directly.
+        let bundle_id = ResourceOrdinal((id >> 4) + 1);
+        let res = kernel32::FindResourceW(instance, bundle_id.as_ptr(), ResourceOrdinal(RT_STRING).as_ptr());
+        if res.is_null() {
+            return last_error();
+        }
+        let data = kernel32::LoadResource(instance, res);
+        if data.is_null() {
+            return last_error();
+        }
+        let data = kernel32::LockResource(data) as *const u16;
+        let size = kernel32::SizeofResource(instance, res);
+        if data.is_null() || size == 0 {
+            return last_error();
+        }
+
+        let bundle = slice::from_raw_parts(data, size as usize / 2);
+        match string_in_bundle(bundle, (id & 0xF) as usize) {
+            Some(wide) => Ok(String::from_utf16_lossy(wide)),
+            None => other_error(&format!("string {} not found", id)),
+        }
+    }
+}
+
+/// Finds a string in an `RT_STRING` bundle, where each of the sixteen strings is a length followed by that many UTF-16 units.  Missing strings have a length of zero.
+fn string_in_bundle(bundle: &[u16], index: usize) -> Option<&[u16]> {
+    let mut rest = bundle;
+    for i in 0..index + 1 {
+        let (&len, tail) = match rest.split_first() {
+            Some(v) => v,
+            None => return None,
+        };
+        let len = len as usize;
+        if len > tail.len() {
+            return None;
+        }
+        if i == index {
+            return if len == 0 { None } else { Some(&tail[..len]) };
+        }
+        rest = &tail[len..];
+    }
+    None
+}
+
+#[cfg(test)]
+#[test]
+fn test_string_in_bundle() {
+    let bundle = [0, 2, b'H' as u16, b'i' as u16, 0, 1, b'!' as u16, 0];
+    assert_eq!(string_in_bundle(&bundle, 0), None);
+    assert_eq!(string_in_bundle(&bundle, 1), Some(&bundle[2..4]));
+    assert_eq!(string_in_bundle(&bundle, 2), None);
+    assert_eq!(string_in_bundle(&bundle, 3), Some(&bundle[6..7]));
+    assert_eq!(string_in_bundle(&bundle, 15), None);
+    assert_eq!(string_in_bundle(&[5, 1], 0), None);
+}
@@ -1,11 +1,15 @@
+use std::cell::{Cell, RefCell};
 use std::io;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
 use kernel32;
 use user32;
 use winapi::*;
-use ::last_error;
-use ::traits::{AsId, IdThunk, AsRaw};
+use ::{last_error, other_error};
+use ::failure::{report_failure, FailureContext, WndProcFailure};
+use ::traits::{AsId, IdThunk, AsRaw, IntoRaw};
 use ::util::{TryDrop, WCString};
+use ::wnd_proc::{def_window_proc, Msg, WindowHandler};
 use super::wnd_class::WndClassId;
 
 custom_derive! {
@@ -148,8 +152,25 @@ pub struct WndBuilder<'a> {
     width: Option<INT>,
     height: Option<INT>,
     wnd_parent: Option<HWND>,
-    menu: Option<HMENU>,
+    menu: Option<Box<MenuThunk + 'a>>,
     param: Option<LPVOID>,
+    handler: Option<Box<WindowHandler>>,
+}
+
+/// Holds on to whatever was passed to `WndBuilder::menu` until the window has taken it over.
+trait MenuThunk {
+    fn as_raw(&self) -> HMENU;
+    fn release(self: Box<Self>);
+}
+
+impl<M> MenuThunk for M where M: IntoRaw<Raw=HMENU> {
+    fn as_raw(&self) -> HMENU {
+        AsRaw::as_raw(self)
+    }
+
+    fn release(self: Box<Self>) {
+        (*self).into_raw();
+    }
 }
 
 impl<'a> WndBuilder<'a> {
@@ -165,6 +186,7 @@ impl<'a> WndBuilder<'a> {
             wnd_parent: None,
             menu: None,
             param: None,
+            handler: None,
         }
     }
 
@@ -224,9 +246,14 @@ impl<'a> WndBuilder<'a> {
         }
     }
 
-    pub fn menu<Menu: AsRaw<Raw=HMENU>>(self, value: Menu) -> Self {
+    /**
+    Sets the window's menu bar or, for child windows, its control id.
+
+    The window takes ownership of the menu once it has been created, and destroys it along with itself.  If creation fails, the menu is destroyed instead.
+    */
+    pub fn menu<Menu: 'a + IntoRaw<Raw=HMENU>>(self, value: Menu) -> Self {
         WndBuilder {
-            menu: Some(value.as_raw()),
+            menu: Some(Box::new(value)),
             ..self
         }
     }
@@ -238,7 +265,24 @@ impl<'a> WndBuilder<'a> {
         }
     }
 
+    /**
+    Attaches `handler` to the window, to be called for each message it receives.
+
+    This requires the window class to use `handler_wnd_proc`, which is the default for `WndClass::new`.  It can't be combined with `param`; `create` fails if both are set.
+    */
+    pub fn handler<H: WindowHandler>(self, handler: H) -> Self {
+        WndBuilder {
+            handler: Some(Box::new(handler)),
+            ..self
+        }
+    }
+
     pub fn create(self) -> io::Result<Wnd> {
+        let has_handler = self.handler.is_some();
+        if has_handler && self.param.is_some() {
+            return other_error("a window can't have both a handler and a param");
+        }
+
         unsafe {
             let ex_style = 0;
             let class_name = self.class_name.expect("missing class_name");
@@ -251,15 +295,148 @@ impl<'a> WndBuilder<'a> {
             let width = self.width.unwrap_or(CW_USEDEFAULT);
             let height = self.height.unwrap_or(CW_USEDEFAULT);
             let wnd_parent = self.wnd_parent.unwrap_or(ptr::null_mut());
-            let menu = self.menu.unwrap_or(ptr::null_mut());
-            let param = self.param.unwrap_or(ptr::null_mut());
-            Wnd::create_raw(ex_style, class_name, window_name,
+            let menu = self.menu.as_ref().map(|m| m.as_raw()).unwrap_or(ptr::null_mut());
+            let handler_param = HandlerParam(Cell::new(self.handler));
+            let param = match self.param {
+                Some(param) => param,
+                None if has_handler => &handler_param as *const HandlerParam as LPVOID,
+                None => ptr::null_mut(),
+            };
+
+            // Windows created while this one is, such as its children, might set their own pending handler.
+            let prev_pending = PENDING_HANDLER.with(|p| p.replace(
+                if has_handler { &handler_param } else { ptr::null() }));
+
+            // If the handler is still in `handler_param` afterwards, it was never attached and gets dropped here.
+            let result = Wnd::create_raw(ex_style, class_name, window_name,
                 style, x, y, width, height,
-                wnd_parent, menu, instance, param)
+                wnd_parent, menu, instance, param);
+
+            PENDING_HANDLER.with(|p| p.set(prev_pending));
+
+            if let Some(thunk) = self.menu {
+                // If creation failed after the menu was attached, the window destroyed it on the way out.
+                if result.is_ok() || user32::IsMenu(menu) == 0 {
+                    thunk.release();
+                }
+            }
+            result
         }
     }
 }
 
+/// What `WndBuilder::create` passes to `CreateWindowExW` when it has a handler.
+struct HandlerParam(Cell<Option<Box<WindowHandler>>>);
+
+thread_local! {
+    /**
+    The `HandlerParam` for the window `WndBuilder::create` is creating on this thread.
+
+    `handler_wnd_proc` only takes a handler from `lpCreateParams` if it is this pointer; anything else belongs to someone else, such as the `MDICREATESTRUCTW` of an MDI child.
+    */
+    static PENDING_HANDLER: Cell<*const HandlerParam> = Cell::new(ptr::null());
+}
+
+/// The per-window state attached by `handler_wnd_proc`.
+struct HandlerState {
+    handler: RefCell<Box<WindowHandler>>,
+
+    /// How many calls to `handler_wnd_proc` for this window are under way.
+    depth: Cell<usize>,
+
+    destroyed: Cell<bool>,
+}
+
+/**
+The window procedure which drives `WindowHandler`s.
+
+On `WM_NCCREATE`, this takes the handler given to `WndBuilder::handler` and stores it in the window's `GWLP_USERDATA`; windows created without a handler just get `DefWindowProcW`.  The handler is dropped after `WM_NCDESTROY`.
+
+# Safety
+
+Windows of a class using this procedure must not have their `GWLP_USERDATA` changed.  Their `lpParam` can be anything; it's only used to recognise the window `WndBuilder::create` is attaching a handler to.
+*/
+pub unsafe extern "system" fn handler_wnd_proc(wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
+    let state_ptr = if message == WM_NCCREATE {
+        let param = (*(l_param as *const CREATESTRUCTW)).lpCreateParams as *const HandlerParam;
+        let pending = PENDING_HANDLER.with(|p| p.get());
+        let handler = if !param.is_null() && param == pending {
+            PENDING_HANDLER.with(|p| p.set(ptr::null()));
+            (*param).0.take()
+        } else {
+            None
+        };
+        match handler {
+            None => ptr::null_mut(),
+            Some(handler) => {
+                let state_ptr = Box::into_raw(Box::new(HandlerState {
+                    handler: RefCell::new(handler),
+                    depth: Cell::new(0),
+                    destroyed: Cell::new(false),
+                }));
+                if set_window_long_ptr(wnd, GWLP_USERDATA, state_ptr).is_err() {
+                    drop(Box::from_raw(state_ptr));
+                    return FALSE as LRESULT;
+                }
+                state_ptr
+            }
+        }
+    } else {
+        match get_window_long_ptr::<_, HandlerState>(wnd, GWLP_USERDATA) {
+            Ok(v) => v as *mut HandlerState,
+            Err(_) => ptr::null_mut(),
+        }
+    };
+
+    let ctx = FailureContext {
+        wnd: wnd,
+        message: message,
+        w_param: w_param,
+        l_param: l_param,
+        dialog: false,
+    };
+
+    // Messages such as `WM_GETMINMAXINFO` arrive before `WM_NCCREATE`.
+    if state_ptr.is_null() {
+        return def_window_proc(wnd, message, w_param, l_param);
+    }
+
+    let state = &*state_ptr;
+    state.depth.set(state.depth.get() + 1);
+
+    // The handler is only borrowed while it runs, so that messages sent by the default processing below reach it too.
+    let outcome = match state.handler.try_borrow_mut() {
+        Err(_) => Ok(Ok(None)),
+        Ok(mut handler) => {
+            let handler = &mut **handler;
+            let msg = Msg::decode(message, w_param, l_param);
+            panic::catch_unwind(AssertUnwindSafe(|| handler.wnd_proc(wnd, msg)))
+        }
+    };
+
+    let result = match outcome {
+        Ok(Ok(Some(result))) => result,
+        Ok(Ok(None)) => def_window_proc(wnd, message, w_param, l_param),
+        Ok(Err(err)) => report_failure(WndProcFailure::Error(err), &ctx),
+        Err(payload) => report_failure(WndProcFailure::Panic(payload), &ctx),
+    };
+
+    if message == WM_NCDESTROY {
+        state.destroyed.set(true);
+        let _ = set_window_long_ptr(wnd, GWLP_USERDATA, ptr::null::<HandlerState>());
+    }
+
+    /*
+    `WM_NCDESTROY` usually arrives while an outer call is still on the stack, such as the one handling `WM_CLOSE`.  The state is dropped once the outermost call is done with it.
+    */
+    state.depth.set(state.depth.get() - 1);
+    if state.destroyed.get() && state.depth.get() == 0 {
+        drop(Box::from_raw(state_ptr));
+    }
+
+    result
+}
+
 pub unsafe fn get_window_long_ptr<W, T>(wnd: W, index: i32) -> io::Result<*const T>
 where W: AsRaw<Raw=HWND>
 {
@@ -267,7 +444,7 @@ where W: AsRaw<Raw=HWND>
     use ::user32::GetWindowLongW as GetWindowLongPtr;
 
     #[cfg(target_pointer_width="64")]
-    use ::user32::GetWindowLongPtrW;
+    use ::user32::GetWindowLongPtrW as GetWindowLongPtr;
 
     // Clear so that we can distinguish from "success, and the value was zero" and "failure".
     kernel32::SetLastError(0);
@@ -312,7 +489,7 @@ where W: AsRaw<Raw=HWND>
     use ::user32::SetWindowLongW as SetWindowLongPtr;
 
     #[cfg(target_pointer_width="64")]
-    use ::user32::SetWindowLongPtrW;
+    use ::user32::SetWindowLongPtrW as SetWindowLongPtr;
 
     // Clear so that we can distinguish from "success, and the last value was zero" and "failure".
     kernel32::SetLastError(0);
@@ -326,3 +503,41 @@ where W: AsRaw<Raw=HWND>
         v => Ok(v as *const T)
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_handler_sees_destroy_after_close() {
+    use std::mem;
+    use std::rc::Rc;
+    use ::dll::get_module_handle;
+    use ::wnd_class::WndClass;
+
+    let wnd_class = WndClass::new()
+        .class_name("wui-test-handler-close")
+        .instance(get_module_handle(None).unwrap())
+        .register()
+        .unwrap();
+
+    let seen = Rc::new(RefCell::new(vec![]));
+    let handler_seen = seen.clone();
+    let wnd = Wnd::new()
+        .class_name(&wnd_class)
+        .window_name("")
+        .style(wnd_style::Overlapped)
+        .handler(move |_: HWND, msg: Msg| {
+            handler_seen.borrow_mut().push(msg.encode().0);
+            Ok(None)
+        })
+        .create()
+        .unwrap();
+
+    // Closing destroys the window, so it mustn't be destroyed again on drop.
+    unsafe { send_message(&wnd, WM_CLOSE, 0, 0).unwrap(); }
+    mem::forget(wnd);
+
+    let seen = Rc::try_unwrap(seen).ok().expect("handler was not dropped after WM_NCDESTROY").into_inner();
+    let close = seen.iter().position(|&m| m == WM_CLOSE).expect("WM_CLOSE did not reach the handler");
+    let destroy = seen.iter().position(|&m| m == WM_DESTROY).expect("WM_DESTROY did not reach the handler");
+    assert!(close < destroy);
+    assert_eq!(seen.last(), Some(&WM_NCDESTROY));
+}
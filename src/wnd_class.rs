@@ -5,8 +5,10 @@ use conv::prelude::*;
 use user32;
 use winapi::*;
 use ::last_error;
+use ::resource_id::{ResourceId, ResourceIdThunk, ResourceOrdinal};
 use ::traits::{AsId, IdThunk, IntoRaw};
 use ::util::{WCString, TryDrop};
+use ::wnd::handler_wnd_proc;
 
 pub type WndProcRef = unsafe extern "system" fn(wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT;
 
@@ -70,7 +72,7 @@ impl<'a> AsId<WndClassId> for &'a WndClass {
 
 impl<'a> IdThunk<WndClassId> for &'a WndClass {
     fn as_id(&self) -> WndClassId {
-        WndClassId(self.0 as LPCWSTR, self.1)
+        WndClassId(ResourceOrdinal(self.0).as_ptr(), self.1)
     }
 }
 
@@ -103,6 +105,46 @@ impl IdThunk<WndClassId> for WCString {
     }
 }
 
+/// A class atom, as returned by `RegisterClassEx`.
+impl AsId<WndClassId> for ATOM {
+    type IdThunk = ATOM;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self
+    }
+}
+
+impl IdThunk<WndClassId> for ATOM {
+    fn as_id(&self) -> WndClassId {
+        WndClassId(ResourceOrdinal(*self).as_ptr(), ptr::null_mut())
+    }
+}
+
+impl AsId<WndClassId> for ResourceOrdinal {
+    type IdThunk = ResourceOrdinal;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self
+    }
+}
+
+impl IdThunk<WndClassId> for ResourceOrdinal {
+    fn as_id(&self) -> WndClassId {
+        WndClassId(self.as_ptr(), ptr::null_mut())
+    }
+}
+
+impl<'a> AsId<WndClassId> for &'a ResourceId {
+    type IdThunk = ResourceIdThunk;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        self.thunk()
+    }
+}
+
+impl IdThunk<WndClassId> for ResourceIdThunk {
+    fn as_id(&self) -> WndClassId {
+        WndClassId(self.as_ptr(), ptr::null_mut())
+    }
+}
+
 pub struct WndClassBuilder {
     style: Option<WndClassStyle>,
     wnd_proc: Option<WndProcRef>,
@@ -137,6 +179,7 @@ impl WndClassBuilder {
         }
     }
 
+    /// Sets the window procedure.  The default is `handler_wnd_proc`, for windows using `WndBuilder::handler`.
     pub fn wnd_proc(self, wnd_proc: WndProcRef) -> WndClassBuilder {
         WndClassBuilder {
             wnd_proc: Some(wnd_proc),
@@ -196,7 +239,7 @@ impl WndClassBuilder {
     pub fn register(self) -> io::Result<WndClass> {
         unsafe {
             let style = self.style.map(|v| v.bits).unwrap_or(0);
-            let wnd_proc = self.wnd_proc.expect("missing wnd_class");
+            let wnd_proc = self.wnd_proc.unwrap_or(handler_wnd_proc);
             let cls_extra = try!(usize_2_int(self.cls_extra.unwrap_or(0)));
             let wnd_extra = try!(usize_2_int(self.wnd_extra.unwrap_or(0)));
             let instance = self.instance.expect("missing instance");
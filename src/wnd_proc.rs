@@ -1,5 +1,8 @@
+use std::io;
 use user32;
 use winapi::*;
+use ::dc::Dc;
+use ::paint::WndPaint;
 
 pub fn def_window_proc(wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
     unsafe {
@@ -7,7 +10,188 @@ pub fn def_window_proc(wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARA
     }
 }
 
-#[derive(Debug)]
+/**
+The Rust side of a window, attached with `WndBuilder::handler`.
+
+Every message sent to the window from `WM_NCCREATE` to `WM_NCDESTROY` is passed to `wnd_proc`, after which the handler is dropped.  By default, `wnd_proc` cracks the message and calls the matching `on_*` method, or `on_message` for anything else.  Closures of the form `FnMut(HWND, Msg) -> io::Result<Option<LRESULT>>` are handlers too, and get every message through `wnd_proc`.
+
+Returning `None` from `wnd_proc` asks for the default processing, which is done by `DefWindowProcW` once the handler has returned; the `on_*` methods do this when they report a message as not handled.  Messages sent by the default processing, such as the `WM_DESTROY` which follows closing the window, come back to the handler as usual.  Only messages sent while the handler itself is running (for example, by showing a message box) go straight to `DefWindowProcW`.  Errors and panics are reported to the failure policy; see `set_failure_policy`.
+*/
+pub trait WindowHandler: 'static {
+    fn wnd_proc(&mut self, wnd: HWND, msg: Msg) -> io::Result<Option<LRESULT>> {
+        use self::Msg::*;
+
+        fn handled(handled: bool) -> Option<LRESULT> {
+            if handled { Some(0) } else { None }
+        }
+
+        match msg {
+            WmCreate { .. } => Ok(Some(if try!(self.on_create(wnd)) { 0 } else { -1 })),
+            WmDestroy => {
+                try!(self.on_destroy(wnd));
+                Ok(Some(0))
+            },
+            // Letting `DefWindowProcW` have `WM_CLOSE` destroys the window.
+            WmClose => Ok(if try!(self.on_close(wnd)) { None } else { Some(0) }),
+            WmPaint => {
+                let ps = try!(WndPaint::begin_paint(wnd));
+                try!(self.on_paint(wnd, ps.dc()));
+                Ok(Some(0))
+            },
+            WmSize { kind, width, height } => self.on_size(wnd, kind, width, height).map(handled),
+            WmCommand(cmd) => self.on_command(wnd, cmd).map(handled),
+            WmTimer { id, .. } => self.on_timer(wnd, id).map(handled),
+            WmKeyDown { vk, repeat, scan, extended } => self.on_key_down(wnd, vk, repeat, scan, extended).map(handled),
+            WmKeyUp { vk, repeat, scan, extended } => self.on_key_up(wnd, vk, repeat, scan, extended).map(handled),
+            WmChar { code, repeat, .. } => self.on_char(wnd, code, repeat).map(handled),
+            WmMouseMove { x, y, keys } => self.on_mouse_move(wnd, x, y, keys).map(handled),
+            WmMouseWheel { delta, x, y, keys } => self.on_mouse_wheel(wnd, delta, x, y, keys).map(handled),
+            msg => match mouse_button(&msg) {
+                Some((button, MouseAction::Down, x, y, keys)) => self.on_mouse_down(wnd, button, x, y, keys).map(handled),
+                Some((button, MouseAction::Up, x, y, keys)) => self.on_mouse_up(wnd, button, x, y, keys).map(handled),
+                Some((button, MouseAction::DblClk, x, y, keys)) => self.on_mouse_dbl_clk(wnd, button, x, y, keys).map(handled),
+                None => self.on_message(wnd, msg),
+            },
+        }
+    }
+
+    /// `WM_CREATE`.  Returns whether to go ahead with creating the window.
+    fn on_create(&mut self, wnd: HWND) -> io::Result<bool> {
+        let _ = wnd;
+        Ok(true)
+    }
+
+    /// `WM_DESTROY`.
+    fn on_destroy(&mut self, wnd: HWND) -> io::Result<()> {
+        let _ = wnd;
+        Ok(())
+    }
+
+    /// `WM_CLOSE`.  Returns whether to allow the window to close, in which case it is destroyed after this returns.
+    fn on_close(&mut self, wnd: HWND) -> io::Result<bool> {
+        let _ = wnd;
+        Ok(true)
+    }
+
+    /// `WM_PAINT`, between `BeginPaint` and `EndPaint`.
+    fn on_paint(&mut self, wnd: HWND, dc: &Dc) -> io::Result<()> {
+        let _ = (wnd, dc);
+        Ok(())
+    }
+
+    /// `WM_SIZE`, with the new size of the client area.  Returns whether the message was handled.
+    fn on_size(&mut self, wnd: HWND, kind: SizeKind, width: u16, height: u16) -> io::Result<bool> {
+        let _ = (wnd, kind, width, height);
+        Ok(false)
+    }
+
+    /// `WM_COMMAND`.  Returns whether the command was handled.
+    fn on_command(&mut self, wnd: HWND, cmd: WmCommand) -> io::Result<bool> {
+        let _ = (wnd, cmd);
+        Ok(false)
+    }
+
+    /// `WM_TIMER`.  Returns whether the message was handled.
+    fn on_timer(&mut self, wnd: HWND, id: WPARAM) -> io::Result<bool> {
+        let _ = (wnd, id);
+        Ok(false)
+    }
+
+    /// `WM_KEYDOWN`.  Returns whether the message was handled.
+    fn on_key_down(&mut self, wnd: HWND, vk: u16, repeat: u16, scan: u8, extended: bool) -> io::Result<bool> {
+        let _ = (wnd, vk, repeat, scan, extended);
+        Ok(false)
+    }
+
+    /// `WM_KEYUP`.  Returns whether the message was handled.
+    fn on_key_up(&mut self, wnd: HWND, vk: u16, repeat: u16, scan: u8, extended: bool) -> io::Result<bool> {
+        let _ = (wnd, vk, repeat, scan, extended);
+        Ok(false)
+    }
+
+    /// `WM_CHAR`, with a UTF-16 code unit.  Returns whether the message was handled.
+    fn on_char(&mut self, wnd: HWND, code: u16, repeat: u16) -> io::Result<bool> {
+        let _ = (wnd, code, repeat);
+        Ok(false)
+    }
+
+    /// `WM_MOUSEMOVE`, in client coordinates.  Returns whether the message was handled.
+    fn on_mouse_move(&mut self, wnd: HWND, x: INT, y: INT, keys: MouseKeys) -> io::Result<bool> {
+        let _ = (wnd, x, y, keys);
+        Ok(false)
+    }
+
+    /// `WM_LBUTTONDOWN`, `WM_RBUTTONDOWN` and `WM_MBUTTONDOWN`, in client coordinates.  Returns whether the message was handled.
+    fn on_mouse_down(&mut self, wnd: HWND, button: MouseButton, x: INT, y: INT, keys: MouseKeys) -> io::Result<bool> {
+        let _ = (wnd, button, x, y, keys);
+        Ok(false)
+    }
+
+    /// `WM_LBUTTONUP`, `WM_RBUTTONUP` and `WM_MBUTTONUP`, in client coordinates.  Returns whether the message was handled.
+    fn on_mouse_up(&mut self, wnd: HWND, button: MouseButton, x: INT, y: INT, keys: MouseKeys) -> io::Result<bool> {
+        let _ = (wnd, button, x, y, keys);
+        Ok(false)
+    }
+
+    /// `WM_LBUTTONDBLCLK`, `WM_RBUTTONDBLCLK` and `WM_MBUTTONDBLCLK`, in client coordinates.  These are only sent if the window class has `DblClks`.  Returns whether the message was handled.
+    fn on_mouse_dbl_clk(&mut self, wnd: HWND, button: MouseButton, x: INT, y: INT, keys: MouseKeys) -> io::Result<bool> {
+        let _ = (wnd, button, x, y, keys);
+        Ok(false)
+    }
+
+    /// `WM_MOUSEWHEEL`, in screen coordinates.  Returns whether the message was handled; if not, the default processing passes it on to the parent window.
+    fn on_mouse_wheel(&mut self, wnd: HWND, delta: i16, x: INT, y: INT, keys: MouseKeys) -> io::Result<bool> {
+        let _ = (wnd, delta, x, y, keys);
+        Ok(false)
+    }
+
+    /// Any message without an `on_*` method of its own.  Returns the message's result, or `None` for the default processing.
+    fn on_message(&mut self, wnd: HWND, msg: Msg) -> io::Result<Option<LRESULT>> {
+        let _ = (wnd, msg);
+        Ok(None)
+    }
+}
+
+impl<F> WindowHandler for F
+where F: 'static + FnMut(HWND, Msg) -> io::Result<Option<LRESULT>> {
+    fn wnd_proc(&mut self, wnd: HWND, msg: Msg) -> io::Result<Option<LRESULT>> {
+        self(wnd, msg)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum MouseAction {
+    Down,
+    Up,
+    DblClk,
+}
+
+fn mouse_button(msg: &Msg) -> Option<(MouseButton, MouseAction, INT, INT, MouseKeys)> {
+    use self::Msg::*;
+    use self::MouseAction::*;
+    use self::MouseButton::*;
+    match *msg {
+        WmLButtonDown { x, y, keys } => Some((Left, Down, x, y, keys)),
+        WmLButtonUp { x, y, keys } => Some((Left, Up, x, y, keys)),
+        WmLButtonDblClk { x, y, keys } => Some((Left, DblClk, x, y, keys)),
+        WmRButtonDown { x, y, keys } => Some((Right, Down, x, y, keys)),
+        WmRButtonUp { x, y, keys } => Some((Right, Up, x, y, keys)),
+        WmRButtonDblClk { x, y, keys } => Some((Right, DblClk, x, y, keys)),
+        WmMButtonDown { x, y, keys } => Some((Middle, Down, x, y, keys)),
+        WmMButtonUp { x, y, keys } => Some((Middle, Up, x, y, keys)),
+        WmMButtonDblClk { x, y, keys } => Some((Middle, DblClk, x, y, keys)),
+        _ => None,
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum WmCommand {
     Menu { id: u16 },
     Accelerator { id: u16 },
@@ -30,3 +214,350 @@ pub fn wm_command(w_param: WPARAM, l_param: LPARAM) -> WmCommand {
         }
     }
 }
+
+impl WmCommand {
+    fn to_params(&self) -> (WPARAM, LPARAM) {
+        use self::WmCommand::*;
+        match *self {
+            Menu { id } => (make_w_param(id, 0), 0),
+            Accelerator { id } => (make_w_param(id, 1), 0),
+            Control { code, id, ctl_wnd } => (make_w_param(id, code), ctl_wnd as LPARAM),
+        }
+    }
+}
+
+const WA_INACTIVE: WORD = 0;
+const WA_ACTIVE: WORD = 1;
+const WA_CLICKACTIVE: WORD = 2;
+
+const KEY_EXTENDED: DWORD = 1 << 24;
+const KEY_CONTEXT: DWORD = 1 << 29;
+const KEY_PREVIOUS_STATE: DWORD = 1 << 30;
+const KEY_TRANSITION: DWORD = 1 << 31;
+
+bitflags! {
+    loose flags MouseKeys, mouse_keys: WORD {
+        const LButton = ::winapi::MK_LBUTTON as ::winapi::WORD,
+        const RButton = ::winapi::MK_RBUTTON as ::winapi::WORD,
+        const Shift = ::winapi::MK_SHIFT as ::winapi::WORD,
+        const Control = ::winapi::MK_CONTROL as ::winapi::WORD,
+        const MButton = ::winapi::MK_MBUTTON as ::winapi::WORD,
+        const XButton1 = ::winapi::MK_XBUTTON1 as ::winapi::WORD,
+        const XButton2 = ::winapi::MK_XBUTTON2 as ::winapi::WORD,
+    }
+}
+
+/// Why a window was resized; see `Msg::WmSize`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SizeKind {
+    Restored,
+    Minimized,
+    Maximized,
+    /// Some other window was restored.
+    MaxShow,
+    /// Some other window was maximized.
+    MaxHide,
+}
+
+/// How a window was activated; see `Msg::WmActivate`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ActivateKind {
+    Inactive,
+    Active,
+    ClickActive,
+}
+
+/**
+A window message, cracked into its parts.
+
+Messages which aren't covered, or whose parameters don't make sense, decode to `Other` with the raw values.  Pointers are passed through as-is; it's up to the window procedure to decide whether they're safe to use.
+
+Coordinates are signed, since windows on monitors to the left of or above the primary one have negative positions.  `WmMouseWheel`, `WmMouseHWheel` and `WmNcHitTest` use screen coordinates; the other mouse messages use client coordinates.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Msg {
+    WmCreate { create_struct: *const CREATESTRUCTW },
+    WmDestroy,
+    WmNcDestroy,
+    WmClose,
+    WmQuit { exit_code: INT },
+    WmPaint,
+    WmEraseBkgnd { dc: HDC },
+    WmMove { x: INT, y: INT },
+    WmSize { kind: SizeKind, width: u16, height: u16 },
+    WmActivate { kind: ActivateKind, minimized: bool, other_wnd: HWND },
+    WmSetFocus { lost_wnd: HWND },
+    WmKillFocus { gained_wnd: HWND },
+    WmEnable { enabled: bool },
+    WmGetMinMaxInfo { info: *mut MINMAXINFO },
+    WmNcHitTest { x: INT, y: INT },
+    WmSetCursor { wnd: HWND, hit_test: i16, mouse_msg: u16 },
+    WmCommand(WmCommand),
+    WmTimer { id: WPARAM, callback: LPARAM },
+    WmKeyDown { vk: u16, repeat: u16, scan: u8, extended: bool },
+    WmKeyUp { vk: u16, repeat: u16, scan: u8, extended: bool },
+    WmSysKeyDown { vk: u16, repeat: u16, scan: u8, extended: bool },
+    WmSysKeyUp { vk: u16, repeat: u16, scan: u8, extended: bool },
+    /// `code` is a UTF-16 code unit; characters outside the BMP arrive as two messages.
+    WmChar { code: u16, repeat: u16, scan: u8, extended: bool },
+    WmSysChar { code: u16, repeat: u16, scan: u8, extended: bool },
+    WmMouseMove { x: INT, y: INT, keys: MouseKeys },
+    WmLButtonDown { x: INT, y: INT, keys: MouseKeys },
+    WmLButtonUp { x: INT, y: INT, keys: MouseKeys },
+    WmLButtonDblClk { x: INT, y: INT, keys: MouseKeys },
+    WmRButtonDown { x: INT, y: INT, keys: MouseKeys },
+    WmRButtonUp { x: INT, y: INT, keys: MouseKeys },
+    WmRButtonDblClk { x: INT, y: INT, keys: MouseKeys },
+    WmMButtonDown { x: INT, y: INT, keys: MouseKeys },
+    WmMButtonUp { x: INT, y: INT, keys: MouseKeys },
+    WmMButtonDblClk { x: INT, y: INT, keys: MouseKeys },
+    /// `delta` is in multiples of `WHEEL_DELTA`, positive away from the user.
+    WmMouseWheel { delta: i16, x: INT, y: INT, keys: MouseKeys },
+    /// `delta` is in multiples of `WHEEL_DELTA`, positive to the right.
+    WmMouseHWheel { delta: i16, x: INT, y: INT, keys: MouseKeys },
+    Other { message: UINT, w_param: WPARAM, l_param: LPARAM },
+}
+
+impl Msg {
+    pub fn decode(message: UINT, w_param: WPARAM, l_param: LPARAM) -> Msg {
+        use self::Msg::*;
+
+        let lo_w = LOWORD(w_param as DWORD);
+        let hi_w = HIWORD(w_param as DWORD);
+        let lo_l = LOWORD(l_param as DWORD);
+        let hi_l = HIWORD(l_param as DWORD);
+        let (x, y) = (GET_X_LPARAM(l_param), GET_Y_LPARAM(l_param));
+        let keys = MouseKeys::from_bits(lo_w);
+        let (repeat, scan, extended) = key_data(l_param);
+
+        match message {
+            WM_CREATE => WmCreate { create_struct: l_param as *const _ },
+            WM_DESTROY => WmDestroy,
+            WM_NCDESTROY => WmNcDestroy,
+            WM_CLOSE => WmClose,
+            WM_QUIT => WmQuit { exit_code: w_param as INT },
+            WM_PAINT => WmPaint,
+            WM_ERASEBKGND => WmEraseBkgnd { dc: w_param as HDC },
+            WM_MOVE => WmMove { x: x, y: y },
+            WM_SIZE => {
+                let kind = match w_param as UINT {
+                    SIZE_RESTORED => SizeKind::Restored,
+                    SIZE_MINIMIZED => SizeKind::Minimized,
+                    SIZE_MAXIMIZED => SizeKind::Maximized,
+                    SIZE_MAXSHOW => SizeKind::MaxShow,
+                    SIZE_MAXHIDE => SizeKind::MaxHide,
+                    _ => return Other { message: message, w_param: w_param, l_param: l_param },
+                };
+                WmSize { kind: kind, width: lo_l, height: hi_l }
+            },
+            WM_ACTIVATE => {
+                let kind = match lo_w {
+                    WA_INACTIVE => ActivateKind::Inactive,
+                    WA_ACTIVE => ActivateKind::Active,
+                    WA_CLICKACTIVE => ActivateKind::ClickActive,
+                    _ => return Other { message: message, w_param: w_param, l_param: l_param },
+                };
+                WmActivate { kind: kind, minimized: hi_w != 0, other_wnd: l_param as HWND }
+            },
+            WM_SETFOCUS => WmSetFocus { lost_wnd: w_param as HWND },
+            WM_KILLFOCUS => WmKillFocus { gained_wnd: w_param as HWND },
+            WM_ENABLE => WmEnable { enabled: w_param != 0 },
+            WM_GETMINMAXINFO => WmGetMinMaxInfo { info: l_param as *mut _ },
+            WM_NCHITTEST => WmNcHitTest { x: x, y: y },
+            WM_SETCURSOR => WmSetCursor { wnd: w_param as HWND, hit_test: lo_l as i16, mouse_msg: hi_l },
+            WM_COMMAND => WmCommand(wm_command(w_param, l_param)),
+            WM_TIMER => WmTimer { id: w_param, callback: l_param },
+            WM_KEYDOWN => WmKeyDown { vk: lo_w, repeat: repeat, scan: scan, extended: extended },
+            WM_KEYUP => WmKeyUp { vk: lo_w, repeat: repeat, scan: scan, extended: extended },
+            WM_SYSKEYDOWN => WmSysKeyDown { vk: lo_w, repeat: repeat, scan: scan, extended: extended },
+            WM_SYSKEYUP => WmSysKeyUp { vk: lo_w, repeat: repeat, scan: scan, extended: extended },
+            WM_CHAR => WmChar { code: lo_w, repeat: repeat, scan: scan, extended: extended },
+            WM_SYSCHAR => WmSysChar { code: lo_w, repeat: repeat, scan: scan, extended: extended },
+            WM_MOUSEMOVE => WmMouseMove { x: x, y: y, keys: keys },
+            WM_LBUTTONDOWN => WmLButtonDown { x: x, y: y, keys: keys },
+            WM_LBUTTONUP => WmLButtonUp { x: x, y: y, keys: keys },
+            WM_LBUTTONDBLCLK => WmLButtonDblClk { x: x, y: y, keys: keys },
+            WM_RBUTTONDOWN => WmRButtonDown { x: x, y: y, keys: keys },
+            WM_RBUTTONUP => WmRButtonUp { x: x, y: y, keys: keys },
+            WM_RBUTTONDBLCLK => WmRButtonDblClk { x: x, y: y, keys: keys },
+            WM_MBUTTONDOWN => WmMButtonDown { x: x, y: y, keys: keys },
+            WM_MBUTTONUP => WmMButtonUp { x: x, y: y, keys: keys },
+            WM_MBUTTONDBLCLK => WmMButtonDblClk { x: x, y: y, keys: keys },
+            WM_MOUSEWHEEL => WmMouseWheel { delta: hi_w as i16, x: x, y: y, keys: keys },
+            WM_MOUSEHWHEEL => WmMouseHWheel { delta: hi_w as i16, x: x, y: y, keys: keys },
+            _ => Other { message: message, w_param: w_param, l_param: l_param },
+        }
+    }
+
+    /**
+    Passes the message to `DefWindowProcW`.
+
+    A `WindowHandler` should return `None` instead, so that any messages the default processing sends reach the handler.
+    */
+    pub fn def_window_proc(&self, wnd: HWND) -> LRESULT {
+        let (message, w_param, l_param) = self.encode();
+        def_window_proc(wnd, message, w_param, l_param)
+    }
+
+    /**
+    Turns the message back into its raw parameters.
+
+    For key messages, the context, previous state and transition bits of `l_param` are filled in from the kind of message.
+    */
+    pub fn encode(&self) -> (UINT, WPARAM, LPARAM) {
+        use self::Msg::*;
+
+        fn mouse(message: UINT, x: INT, y: INT, keys: MouseKeys) -> (UINT, WPARAM, LPARAM) {
+            (message, keys.bits() as WPARAM, make_point(x, y))
+        }
+
+        fn key(message: UINT, code: u16, repeat: u16, scan: u8, extended: bool, flags: DWORD) -> (UINT, WPARAM, LPARAM) {
+            let mut l_param = repeat as DWORD | (scan as DWORD) << 16 | flags;
+            if extended {
+                l_param |= KEY_EXTENDED;
+            }
+            (message, code as WPARAM, l_param as LPARAM)
+        }
+
+        match *self {
+            WmCreate { create_struct } => (WM_CREATE, 0, create_struct as LPARAM),
+            WmDestroy => (WM_DESTROY, 0, 0),
+            WmNcDestroy => (WM_NCDESTROY, 0, 0),
+            WmClose => (WM_CLOSE, 0, 0),
+            WmQuit { exit_code } => (WM_QUIT, exit_code as WPARAM, 0),
+            WmPaint => (WM_PAINT, 0, 0),
+            WmEraseBkgnd { dc } => (WM_ERASEBKGND, dc as WPARAM, 0),
+            WmMove { x, y } => (WM_MOVE, 0, make_point(x, y)),
+            WmSize { kind, width, height } => {
+                let kind = match kind {
+                    SizeKind::Restored => SIZE_RESTORED,
+                    SizeKind::Minimized => SIZE_MINIMIZED,
+                    SizeKind::Maximized => SIZE_MAXIMIZED,
+                    SizeKind::MaxShow => SIZE_MAXSHOW,
+                    SizeKind::MaxHide => SIZE_MAXHIDE,
+                };
+                (WM_SIZE, kind as WPARAM, make_l_param(width, height))
+            },
+            WmActivate { kind, minimized, other_wnd } => {
+                let kind = match kind {
+                    ActivateKind::Inactive => WA_INACTIVE,
+                    ActivateKind::Active => WA_ACTIVE,
+                    ActivateKind::ClickActive => WA_CLICKACTIVE,
+                };
+                (WM_ACTIVATE, make_w_param(kind, minimized as WORD), other_wnd as LPARAM)
+            },
+            WmSetFocus { lost_wnd } => (WM_SETFOCUS, lost_wnd as WPARAM, 0),
+            WmKillFocus { gained_wnd } => (WM_KILLFOCUS, gained_wnd as WPARAM, 0),
+            WmEnable { enabled } => (WM_ENABLE, enabled as WPARAM, 0),
+            WmGetMinMaxInfo { info } => (WM_GETMINMAXINFO, 0, info as LPARAM),
+            WmNcHitTest { x, y } => (WM_NCHITTEST, 0, make_point(x, y)),
+            WmSetCursor { wnd, hit_test, mouse_msg } =>
+                (WM_SETCURSOR, wnd as WPARAM, make_l_param(hit_test as u16, mouse_msg)),
+            WmCommand(ref cmd) => {
+                let (w_param, l_param) = cmd.to_params();
+                (WM_COMMAND, w_param, l_param)
+            },
+            WmTimer { id, callback } => (WM_TIMER, id, callback),
+            WmKeyDown { vk, repeat, scan, extended } =>
+                key(WM_KEYDOWN, vk, repeat, scan, extended, 0),
+            WmKeyUp { vk, repeat, scan, extended } =>
+                key(WM_KEYUP, vk, repeat, scan, extended, KEY_PREVIOUS_STATE | KEY_TRANSITION),
+            WmSysKeyDown { vk, repeat, scan, extended } =>
+                key(WM_SYSKEYDOWN, vk, repeat, scan, extended, KEY_CONTEXT),
+            WmSysKeyUp { vk, repeat, scan, extended } =>
+                key(WM_SYSKEYUP, vk, repeat, scan, extended, KEY_CONTEXT | KEY_PREVIOUS_STATE | KEY_TRANSITION),
+            WmChar { code, repeat, scan, extended } =>
+                key(WM_CHAR, code, repeat, scan, extended, 0),
+            WmSysChar { code, repeat, scan, extended } =>
+                key(WM_SYSCHAR, code, repeat, scan, extended, KEY_CONTEXT),
+            WmMouseMove { x, y, keys } => mouse(WM_MOUSEMOVE, x, y, keys),
+            WmLButtonDown { x, y, keys } => mouse(WM_LBUTTONDOWN, x, y, keys),
+            WmLButtonUp { x, y, keys } => mouse(WM_LBUTTONUP, x, y, keys),
+            WmLButtonDblClk { x, y, keys } => mouse(WM_LBUTTONDBLCLK, x, y, keys),
+            WmRButtonDown { x, y, keys } => mouse(WM_RBUTTONDOWN, x, y, keys),
+            WmRButtonUp { x, y, keys } => mouse(WM_RBUTTONUP, x, y, keys),
+            WmRButtonDblClk { x, y, keys } => mouse(WM_RBUTTONDBLCLK, x, y, keys),
+            WmMButtonDown { x, y, keys } => mouse(WM_MBUTTONDOWN, x, y, keys),
+            WmMButtonUp { x, y, keys } => mouse(WM_MBUTTONUP, x, y, keys),
+            WmMButtonDblClk { x, y, keys } => mouse(WM_MBUTTONDBLCLK, x, y, keys),
+            WmMouseWheel { delta, x, y, keys } =>
+                (WM_MOUSEWHEEL, make_w_param(keys.bits(), delta as u16), make_point(x, y)),
+            WmMouseHWheel { delta, x, y, keys } =>
+                (WM_MOUSEHWHEEL, make_w_param(keys.bits(), delta as u16), make_point(x, y)),
+            Other { message, w_param, l_param } => (message, w_param, l_param),
+        }
+    }
+}
+
+/// Splits the `l_param` of a key or character message into the repeat count, scan code and extended flag.
+fn key_data(l_param: LPARAM) -> (u16, u8, bool) {
+    let l_param = l_param as DWORD;
+    (LOWORD(l_param), (l_param >> 16) as u8, l_param & KEY_EXTENDED != 0)
+}
+
+fn make_w_param(lo: WORD, hi: WORD) -> WPARAM {
+    ((hi as DWORD) << 16 | lo as DWORD) as WPARAM
+}
+
+fn make_l_param(lo: WORD, hi: WORD) -> LPARAM {
+    ((hi as DWORD) << 16 | lo as DWORD) as LPARAM
+}
+
+/// The inverse of `GET_X_LPARAM` and `GET_Y_LPARAM`.
+fn make_point(x: INT, y: INT) -> LPARAM {
+    make_l_param(x as i16 as WORD, y as i16 as WORD)
+}
+
+#[cfg(test)]
+#[test]
+fn test_msg_decode_encode() {
+    use std::ptr;
+    use self::Msg::*;
+
+    let no_keys = MouseKeys::empty();
+    let msgs = [
+        WmDestroy,
+        WmQuit { exit_code: 3 },
+        WmMove { x: -1920, y: -8 },
+        WmSize { kind: SizeKind::Maximized, width: 1024, height: 768 },
+        WmActivate { kind: ActivateKind::ClickActive, minimized: true, other_wnd: ptr::null_mut() },
+        WmEnable { enabled: true },
+        WmNcHitTest { x: -5, y: 1200 },
+        WmSetCursor { wnd: ptr::null_mut(), hit_test: -2, mouse_msg: WM_LBUTTONDOWN as u16 },
+        WmCommand(self::WmCommand::Menu { id: 100 }),
+        WmCommand(self::WmCommand::Accelerator { id: 200 }),
+        WmKeyDown { vk: VK_F1 as u16, repeat: 1, scan: 0x3B, extended: false },
+        WmSysKeyUp { vk: VK_RIGHT as u16, repeat: 1, scan: 0x4D, extended: true },
+        WmChar { code: 'a' as u16, repeat: 2, scan: 0x1E, extended: false },
+        WmMouseMove { x: -1, y: -1, keys: no_keys },
+        WmLButtonDown { x: 10, y: -20, keys: mouse_keys::LButton | mouse_keys::Control },
+        WmMouseWheel { delta: -120, x: -100, y: 50, keys: mouse_keys::Shift },
+        Other { message: WM_USER + 1, w_param: 7, l_param: -1 },
+    ];
+    for msg in &msgs {
+        let (message, w_param, l_param) = msg.encode();
+        assert_eq!(Msg::decode(message, w_param, l_param), *msg);
+    }
+
+    // Coordinates are sign-extended from each half of `l_param`.
+    assert_eq!(Msg::decode(WM_MOUSEMOVE, MK_RBUTTON, 0xFB2E_FFFF_u32 as LPARAM),
+        WmMouseMove { x: -1, y: -1234, keys: mouse_keys::RButton });
+    assert_eq!(Msg::decode(WM_MOUSEMOVE, 0, -1), WmMouseMove { x: -1, y: -1, keys: no_keys });
+    assert_eq!(WmMove { x: -1, y: 2 }.encode(), (WM_MOVE, 0, 0x0002_FFFF));
+
+    // Key messages carry the usual state bits.
+    assert_eq!(WmKeyUp { vk: 0x41, repeat: 1, scan: 0x1E, extended: false }.encode(),
+        (WM_KEYUP, 0x41, 0xC01E_0001_u32 as LPARAM));
+
+    // Mouse button messages are split into button and action.
+    assert_eq!(mouse_button(&Msg::decode(WM_RBUTTONUP, MK_CONTROL, make_point(-3, 40))),
+        Some((MouseButton::Right, MouseAction::Up, -3, 40, mouse_keys::Control)));
+    assert_eq!(mouse_button(&Msg::decode(WM_MBUTTONDBLCLK, 0, 0)),
+        Some((MouseButton::Middle, MouseAction::DblClk, 0, 0, no_keys)));
+    assert_eq!(mouse_button(&Msg::decode(WM_LBUTTONDOWN, MK_LBUTTON, make_point(5, 6))),
+        Some((MouseButton::Left, MouseAction::Down, 5, 6, mouse_keys::LButton)));
+    assert_eq!(mouse_button(&WmMouseMove { x: 1, y: 2, keys: no_keys }), None);
+
+    // Parameters outside the documented range aren't cracked.
+    assert_eq!(Msg::decode(WM_SIZE, 9, 0), Other { message: WM_SIZE, w_param: 9, l_param: 0 });
+}
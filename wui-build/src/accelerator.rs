@@ -0,0 +1,36 @@
+/*!
+Keyboard accelerator tables.
+
+The accelerator model, its parser and its serialization come from `wui-template`, which `wui` also uses to parse accelerators at runtime.
+*/
+use std::fmt;
+use ::res::{ResEntry, MEMFLAG_MOVEABLE, MEMFLAG_PURE};
+use ::resource::RT_ACCELERATOR;
+
+pub use wui_template::{accelerator_table_data, Accelerator, FALT, FCONTROL, FNOINVERT, FSHIFT, FVIRTKEY};
+
+/// An accelerator table, stored as an `RT_ACCELERATOR` resource.
+pub struct AcceleratorResource {
+    pub name_id: u16,
+    pub entries: Vec<Accelerator>,
+}
+
+impl AcceleratorResource {
+    pub fn entry(&self) -> ResEntry {
+        ResEntry {
+            memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_PURE,
+            ..ResEntry::new(RT_ACCELERATOR, self.name_id, accelerator_table_data(&self.entries))
+        }
+    }
+}
+
+impl fmt::Display for AcceleratorResource {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(fmt, "{} ACCELERATORS", self.name_id));
+        try!(writeln!(fmt, "BEGIN"));
+        for accel in &self.entries {
+            try!(writeln!(fmt, "    {}", accel));
+        }
+        writeln!(fmt, "END")
+    }
+}
@@ -0,0 +1,302 @@
+/*!
+Assembling resources in a build script.
+*/
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use gcc;
+use ::{io_err, Target, Toolchain};
+use ::accelerator::AcceleratorResource;
+use ::cargo::CargoManifest;
+use ::coff::write_coff;
+use ::dialog::DialogResource;
+use ::ids::{ResourceKind, ID_MODULE_NAME, write_id_module};
+use ::manifest::{Assembly, manifest_from_cargo, merge_manifest, read_manifest};
+use ::menu::MenuResource;
+use ::resource::{
+    ResourceScript, VersionInfo, IconResource, StringTable, LocaleId,
+    UserDefined, UserData, CREATEPROCESS_MANIFEST_RESOURCE_ID, RT_MANIFEST,
+};
+
+const DEFAULT_OUTPUT_NAME: &'static str = "wui-generated-resource";
+
+/**
+Collects resources, then compiles and links them into the package being built.
+
+```ignore
+ResourceBuilder::guess().unwrap()
+    .icon(1, "res/app.ico")
+    .name(ResourceKind::Icon, 1, "APP_ICON")
+    .string(LCID_EN_US_UTF_16, 100, "Hello!")
+    .compile().unwrap();
+```
+*/
+pub struct ResourceBuilder {
+    script: ResourceScript,
+    manifest: Option<Assembly>,
+    output_name: String,
+
+    /// Files read while building up the resources, rather than during `compile`.
+    inputs: Vec<String>,
+
+    names: HashMap<(ResourceKind, u16), String>,
+
+    /// Existing scripts to compile with an external resource compiler.
+    rc_files: Vec<String>,
+}
+
+impl ResourceBuilder {
+    /// Starts with no resources at all, not even a manifest.
+    pub fn new() -> ResourceBuilder {
+        ResourceBuilder::from_script(ResourceScript::new())
+    }
+
+    /// Starts with the version information guessed from the package's manifest, and an application manifest that enables common controls 6.
+    pub fn guess() -> io::Result<ResourceBuilder> {
+        // Loading the package's manifest runs `cargo metadata`, so only do it once.
+        let carman = try!(CargoManifest::new());
+        Ok(ResourceBuilder {
+            manifest: Some(try!(manifest_from_cargo(&carman, true))),
+            ..ResourceBuilder::from_script(try!(ResourceScript::from_manifest(&carman)))
+        })
+    }
+
+    pub fn from_script(script: ResourceScript) -> ResourceBuilder {
+        ResourceBuilder {
+            script: script,
+            manifest: None,
+            output_name: DEFAULT_OUTPUT_NAME.into(),
+            inputs: vec![],
+            names: HashMap::new(),
+            rc_files: vec![],
+        }
+    }
+
+    pub fn version_info(mut self, version_info: VersionInfo) -> Self {
+        self.script.version_info = Some(version_info);
+        self
+    }
+
+    /// Adds an icon, replacing any existing icon with the same id.
+    pub fn icon<P: Into<String>>(mut self, name_id: u16, path: P) -> Self {
+        self.script.icons.retain(|v| v.name_id != name_id);
+        self.script.icons.push(IconResource {
+            name_id: name_id,
+            path: path.into(),
+        });
+        self
+    }
+
+    /// Adds a string, replacing any existing string with the same locale and id.
+    pub fn string<S: Into<String>>(mut self, lcid: LocaleId, id: u16, string: S) -> Self {
+        self.script.add_string(lcid, id, string);
+        self
+    }
+
+    /// Replaces the whole string table for a locale.
+    pub fn string_table(mut self, lcid: LocaleId, table: StringTable) -> Self {
+        self.script.string_tables.insert(lcid, table);
+        self
+    }
+
+    pub fn dialog(mut self, dialog: DialogResource) -> Self {
+        self.script.dialogs.retain(|v| v.name_id != dialog.name_id);
+        self.script.dialogs.push(dialog);
+        self
+    }
+
+    pub fn menu(mut self, menu: MenuResource) -> Self {
+        self.script.menus.retain(|v| v.name_id != menu.name_id);
+        self.script.menus.push(menu);
+        self
+    }
+
+    pub fn accelerators(mut self, accels: AcceleratorResource) -> Self {
+        self.script.accelerators.retain(|v| v.name_id != accels.name_id);
+        self.script.accelerators.push(accels);
+        self
+    }
+
+    /// Sets the application manifest, replacing the guessed one.
+    pub fn manifest(mut self, manifest: Assembly) -> Self {
+        self.manifest = Some(manifest);
+        self
+    }
+
+    /**
+    Reads an existing manifest file and merges it into the current manifest, if there is one.
+
+    This fails if the file can't be parsed, or if it conflicts with the current manifest; see `merge_manifest`.
+    */
+    pub fn manifest_file<P: Into<String>>(mut self, path: P) -> io::Result<Self> {
+        let path = path.into();
+        let existing = try!(read_manifest(&path));
+        self.manifest = Some(match self.manifest.take() {
+            Some(current) => try!(merge_manifest(current, existing)),
+            None => existing,
+        });
+        self.inputs.push(path);
+        Ok(self)
+    }
+
+    pub fn no_manifest(mut self) -> Self {
+        self.manifest = None;
+        self
+    }
+
+    /// Adds a user-defined resource, replacing any existing one with the same type and id.
+    pub fn user_defined(mut self, ud: UserDefined) -> Self {
+        self.script.user_defined
+            .retain(|v| !(v.type_id == ud.type_id && v.name_id == ud.name_id));
+        self.script.user_defined.push(ud);
+        self
+    }
+
+    /// Sets the name of the generated object (without extension).  This only needs changing if a package compiles more than one set of resources.
+    pub fn output_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.output_name = name.into();
+        self
+    }
+
+    /**
+    Names the constant generated for a resource.
+
+    Resources that aren't named get constants like `ICON_1`; see `write_id_module`.
+    */
+    pub fn name<S: Into<String>>(mut self, kind: ResourceKind, id: u16, name: S) -> Self {
+        self.names.insert((kind, id), name.into());
+        self
+    }
+
+    /**
+    Compiles an existing resource script with an external resource compiler, found with `Target::find_rc`, and links it in along with everything else.
+
+    Its ids mustn't clash with the generated resources, which include the version information and manifest unless they're turned off.  Ids in the script don't get constants, and only the script itself is watched for changes; build scripts should emit `cargo:rerun-if-changed` for anything it includes.
+    */
+    pub fn rc_file<P: Into<String>>(mut self, path: P) -> Self {
+        self.rc_files.push(path.into());
+        self
+    }
+
+    /// Gives access to everything that doesn't have a dedicated method.
+    pub fn script_mut(&mut self) -> &mut ResourceScript {
+        &mut self.script
+    }
+
+    /// The resource script as it stands, including the manifest.
+    pub fn into_script(self) -> ResourceScript {
+        let mut script = self.script;
+        if let Some(manifest) = self.manifest {
+            script.user_defined.retain(|v|
+                !(v.type_id == RT_MANIFEST && v.name_id == CREATEPROCESS_MANIFEST_RESOURCE_ID));
+            script.user_defined.push(UserDefined {
+                name_id: CREATEPROCESS_MANIFEST_RESOURCE_ID,
+                type_id: RT_MANIFEST,
+                data: UserData::Data(format!("{}", manifest).into()),
+            });
+        }
+        script
+    }
+
+    /**
+    Compiles the resources and tells Cargo to link them in.
+
+    This must be called from a build script.  It also emits `cargo:rerun-if-changed` for every file the resources are read from, and writes constants for the resource ids to `OUT_DIR/wui_resources.rs`.
+    */
+    pub fn compile(self) -> io::Result<()> {
+        let output_name = self.output_name.clone();
+        let inputs = self.inputs.clone();
+        let names = self.names.clone();
+        let rc_files = self.rc_files.clone();
+        let rc = self.into_script();
+
+        for path in inputs.iter().chain(&rc_files).map(|p| &p[..]).chain(rc.input_paths()) {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+
+        let out_dir = try!(env::var("OUT_DIR")
+            .map_err(|_| io_err("could not read OUT_DIR")));
+
+        println!("wui-build: Writing resource ids...");
+        let mut ids_file = try!(fs::File::create(Path::new(&out_dir).join(ID_MODULE_NAME)));
+        try!(write_id_module(&rc, &names, &mut ids_file));
+        drop(ids_file);
+
+        println!("wui-build: Guessing target...");
+        let target = try!(Target::guess());
+
+        println!("wui-build: Writing resource object...");
+        let res_path = Path::new(&out_dir)
+            .join(&output_name).with_extension(target.res_ext());
+        let entries = try!(rc.entries());
+        let mut res_file = try!(fs::File::create(&res_path));
+        try!(write_coff(&entries, try!(target.machine()), &mut res_file));
+        drop(res_file);
+        link_object(&target, &out_dir, &output_name, &res_path);
+
+        if !rc_files.is_empty() {
+            let tool = try!(target.find_rc());
+            for (i, rc_file) in rc_files.iter().enumerate() {
+                println!("wui-build: Compiling `{}`...", rc_file);
+                let name = format!("{}-rc{}", output_name, i);
+                let obj_path = Path::new(&out_dir).join(&name).with_extension(target.res_ext());
+                try!(tool.compile(try!(target.machine()), Path::new(rc_file), &obj_path));
+                link_object(&target, &out_dir, &name, &obj_path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Tells Cargo to link a resource object in, under a library name unique to it.
+fn link_object(target: &Target, out_dir: &str, name: &str, path: &Path) {
+    /*
+    Cargo is a pain, so we have to be tricky about this.  The fundamental problem is that we can't pass arbitrary objects/options to the linker.  As a result, we have to trick Cargo into letting us link the resource object in.
+
+    For the GNU toolchain, we do this by compiling the resource object into a static library, then link that static library into the final object.
+
+    `LINK`, on the other hand, *will not* carry resources in a static library through to the final output, so we need to pass the resource object file directly to the linker.  Except that, as noted before, Cargo is so dogmatic that *we can't do this*.  The *only* way I could get this to work is to forcibly name the object file `*.dll`, then lie to Cargo that it's a dynamic library *and hope no one notices*.
+    */
+    match target.toolchain {
+        Toolchain::MinGW | Toolchain::GnuLlvm => {
+            println!("wui-build: Linking resource library...");
+            gcc::Config::new()
+                .object(path)
+                .compile(&format!("lib{}.a", name));
+        },
+        Toolchain::Msvc => {
+            println!("cargo:rustc-link-lib=dylib={}", name);
+            println!("cargo:rustc-link-search=native={}", out_dir);
+        },
+    }
+}
+
+impl Default for ResourceBuilder {
+    fn default() -> ResourceBuilder {
+        ResourceBuilder::new()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_builder_input_paths() {
+    let rc = ResourceBuilder::new()
+        .icon(1, "old.ico")
+        .icon(2, "other.ico")
+        .icon(1, "new.ico")
+        .user_defined(UserDefined {
+            name_id: 1,
+            type_id: 256,
+            data: UserData::Path("data.bin".into()),
+        })
+        .user_defined(UserDefined {
+            name_id: 2,
+            type_id: 256,
+            data: UserData::Data(vec![1, 2, 3]),
+        })
+        .into_script();
+    assert_eq!(rc.input_paths(), vec!["other.ico", "new.ico", "data.bin"]);
+}
@@ -1,47 +1,305 @@
+use std::env;
 use std::io;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use rustc_serialize::json::{self, Json};
 use ::io_err;
+use ::util::read_file;
 
+/**
+The parts of the package's manifest that are used to describe the resulting executable.
+
+These come from the `CARGO_PKG_*` environment variables where Cargo provides them, and from `cargo metadata` otherwise.
+*/
 pub struct CargoManifest {
-    manifest: Json,
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub authors: Vec<String>,
+    pub homepage: Option<String>,
+    pub license: Option<String>,
+    pub repository: Option<String>,
+
+    /// The directory containing `Cargo.toml`.
+    pub manifest_dir: PathBuf,
+
+    /// The contents of the `[package.metadata.wui]` table.
+    pub wui: WuiMetadata,
+}
+
+/**
+Optional settings from the `[package.metadata.wui]` table of `Cargo.toml`.
+
+```toml
+[package.metadata.wui]
+company = "Example Ltd."
+copyright = "Copyright © 2016 Example Ltd."
+trademarks = "Example™ is a trademark of Example Ltd."
+original-filename = "example.exe"
+build-number = "git"
+```
+
+See `BuildNumber` for the values `build-number` can take.
+*/
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct WuiMetadata {
+    /// The `CompanyName` in the version information.  This is left out if not given; `authors` lists people, not a company, so they go in `Comments` instead.
+    pub company: Option<String>,
+    pub copyright: Option<String>,
+    pub trademarks: Option<String>,
+    pub original_filename: Option<String>,
+    pub build_number: BuildNumber,
+
+    /**
+    Describes how a private build differs from the normal one.
+
+    This is never read from `Cargo.toml`, since it describes a particular build rather than the package; it comes from the `WUI_PRIVATE_BUILD` environment variable.
+    */
+    pub private_build: Option<String>,
+}
+
+/**
+Where the fourth component of the file and product versions comes from.
+
+In `[package.metadata.wui]`, this is written as `build-number = "env:NAME"`, `"file:PATH"` or `"git"`.
+*/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BuildNumber {
+    /// Always zero.  This is the default.
+    Zero,
+
+    /// Read from the named environment variable; zero if it isn't set.
+    Env(String),
+
+    /// Read from a file, relative to the package directory.  wui-build only reads the file; incrementing it is up to you.
+    File(PathBuf),
+
+    /// The number of commits since the most recent tag, according to `git describe`.
+    GitCommitsSinceTag,
+}
+
+impl Default for BuildNumber {
+    fn default() -> Self {
+        BuildNumber::Zero
+    }
+}
+
+impl BuildNumber {
+    pub fn parse(s: &str) -> io::Result<BuildNumber> {
+        if s == "git" {
+            Ok(BuildNumber::GitCommitsSinceTag)
+        } else if s.starts_with("env:") && s.len() > 4 {
+            Ok(BuildNumber::Env(s[4..].into()))
+        } else if s.starts_with("file:") && s.len() > 5 {
+            Ok(BuildNumber::File(s[5..].into()))
+        } else {
+            Err(io_err(format!("invalid build number source `{}`; expected `env:NAME`, `file:PATH` or `git`", s)))
+        }
+    }
+
+    /**
+    Works out the build number for the package in `manifest_dir`.
+
+    This also tells Cargo to re-run the build script when the source changes.
+    */
+    pub fn resolve(&self, manifest_dir: &Path) -> io::Result<u16> {
+        match *self {
+            BuildNumber::Zero => Ok(0),
+            BuildNumber::Env(ref name) => {
+                println!("cargo:rerun-if-env-changed={}", name);
+                match env_var(name) {
+                    Some(v) => parse_build_number(&v, &format!("environment variable `{}`", name)),
+                    None => Ok(0),
+                }
+            },
+            BuildNumber::File(ref path) => {
+                let path = manifest_dir.join(path);
+                println!("cargo:rerun-if-changed={}", path.display());
+                let path_str = try!(path.to_str()
+                    .ok_or_else(|| io_err(format!("build number path `{}` is not valid UTF-8", path.display()))));
+                let contents = try!(read_file(path_str));
+                let contents = String::from_utf8_lossy(&contents);
+                parse_build_number(contents.trim(), &format!("`{}`", path_str))
+            },
+            BuildNumber::GitCommitsSinceTag => {
+                try!(rerun_if_git_changed(manifest_dir));
+                let output = try!(Command::new("git")
+                    .arg("describe").arg("--tags").arg("--long")
+                    .current_dir(manifest_dir)
+                    .output());
+                if !output.status.success() {
+                    return Err(io_err(format!("error running `git describe`: {}",
+                        String::from_utf8_lossy(&output.stderr).trim())));
+                }
+                let desc = String::from_utf8_lossy(&output.stdout);
+                commits_since_tag(desc.trim())
+            },
+        }
+    }
+}
+
+/// Tells Cargo to re-run the build script when `HEAD` moves, or a branch or tag changes.
+fn rerun_if_git_changed(manifest_dir: &Path) -> io::Result<()> {
+    let output = try!(Command::new("git")
+        .arg("rev-parse").arg("--git-dir")
+        .current_dir(manifest_dir)
+        .output());
+    if !output.status.success() {
+        return Err(io_err(format!("error running `git rev-parse`: {}",
+            String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    let git_dir = manifest_dir.join(String::from_utf8_lossy(&output.stdout).trim());
+
+    println!("cargo:rerun-if-changed={}", git_dir.join("HEAD").display());
+    println!("cargo:rerun-if-changed={}", git_dir.join("refs").display());
+    // Cargo treats a missing file as always changed, and `packed-refs` only exists once git has packed something.
+    let packed_refs = git_dir.join("packed-refs");
+    if packed_refs.exists() {
+        println!("cargo:rerun-if-changed={}", packed_refs.display());
+    }
+    Ok(())
+}
+
+fn parse_build_number(s: &str, source: &str) -> io::Result<u16> {
+    match s.parse::<u64>() {
+        Ok(n) if n <= u16::MAX as u64 => Ok(n as u16),
+        Ok(n) => Err(io_err(format!("build number {} from {} is larger than {}", n, source, u16::MAX))),
+        Err(_) => Err(io_err(format!("build number `{}` from {} is not a number", s, source))),
+    }
+}
+
+/// Extracts the commit count from `git describe --long` output, which looks like `TAG-COUNT-gHASH`.
+fn commits_since_tag(desc: &str) -> io::Result<u16> {
+    let mut parts = desc.rsplitn(3, '-');
+    let count = match (parts.next(), parts.next(), parts.next()) {
+        (Some(hash), Some(count), Some(_)) if hash.starts_with('g') => count,
+        _ => return Err(io_err(format!("unexpected `git describe` output `{}`", desc))),
+    };
+    parse_build_number(count, "`git describe`")
 }
 
 impl CargoManifest {
     pub fn new() -> io::Result<Self> {
-        Ok(CargoManifest {
-            manifest: try!(cargo_read_manifest()),
-        })
+        let manifest_dir = env::var_os("CARGO_MANIFEST_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let manifest_path = manifest_dir.join("Cargo.toml");
+        println!("cargo:rerun-if-changed={}", manifest_path.display());
+        let metadata = try!(cargo_metadata(&manifest_path));
+        let mut carman = try!(CargoManifest::from_metadata(&metadata, env_var("CARGO_PKG_NAME")));
+
+        if let Some(v) = env_var("CARGO_PKG_NAME") { carman.name = v; }
+        if let Some(v) = env_var("CARGO_PKG_VERSION") { carman.version = v; }
+        if let Some(v) = env_var("CARGO_PKG_DESCRIPTION") { carman.description = Some(v); }
+        if let Some(v) = env_var("CARGO_PKG_AUTHORS") {
+            carman.authors = v.split(':').map(String::from).collect();
+        }
+        if let Some(v) = env_var("CARGO_PKG_HOMEPAGE") { carman.homepage = Some(v); }
+        if let Some(v) = env_var("CARGO_PKG_LICENSE") { carman.license = Some(v); }
+        if let Some(v) = env_var("CARGO_PKG_REPOSITORY") { carman.repository = Some(v); }
+        if let Some(v) = env_var("CARGO_MANIFEST_DIR") { carman.manifest_dir = v.into(); }
+
+        println!("cargo:rerun-if-env-changed=WUI_PRIVATE_BUILD");
+        carman.wui.private_build = env_var("WUI_PRIVATE_BUILD");
+
+        Ok(carman)
     }
 
-    pub fn package_name(&self) -> io::Result<&str> {
-        let manifest = &self.manifest;
-        let name = try!(manifest.find_path(&["name"])
-            .ok_or_else(|| io_err("could not find package name in manifest")));
-        name.as_string()
-            .ok_or_else(|| io_err("package name is not a string"))
+    /**
+    Extracts a package from the output of `cargo metadata`.
+
+    If `name` is not given, the output must contain exactly one package.
+    */
+    fn from_metadata(metadata: &Json, name: Option<String>) -> io::Result<Self> {
+        let packages = try!(metadata.find("packages")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| io_err("could not find packages in `cargo metadata` output")));
+
+        let package = match name {
+            Some(ref name) => packages.iter()
+                .find(|p| p.find("name").and_then(|v| v.as_string()) == Some(name)),
+            None if packages.len() == 1 => packages.first(),
+            None => None,
+        };
+        let package = try!(package
+            .ok_or_else(|| io_err("could not find package in `cargo metadata` output")));
+
+        let wui = match package.find_path(&["metadata", "wui"]) {
+            Some(wui) => WuiMetadata {
+                company: try!(opt_string(wui, "company")),
+                copyright: try!(opt_string(wui, "copyright")),
+                trademarks: try!(opt_string(wui, "trademarks")),
+                original_filename: try!(opt_string(wui, "original-filename")),
+                build_number: match try!(opt_string(wui, "build-number")) {
+                    Some(s) => try!(BuildNumber::parse(&s)),
+                    None => BuildNumber::Zero,
+                },
+                private_build: None,
+            },
+            None => WuiMetadata::default(),
+        };
+
+        let authors = match package.find("authors").and_then(|v| v.as_array()) {
+            Some(authors) => authors.iter()
+                .filter_map(|v| v.as_string())
+                .map(String::from)
+                .collect(),
+            None => vec![],
+        };
+
+        let manifest_dir = try!(opt_string(package, "manifest_path"))
+            .as_ref()
+            .and_then(|p| Path::new(p).parent())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        Ok(CargoManifest {
+            name: try!(try!(opt_string(package, "name"))
+                .ok_or_else(|| io_err("could not find package name in manifest"))),
+            version: try!(try!(opt_string(package, "version"))
+                .ok_or_else(|| io_err("could not find package version in manifest"))),
+            description: try!(opt_string(package, "description")),
+            authors: authors,
+            homepage: try!(opt_string(package, "homepage")),
+            license: try!(opt_string(package, "license")),
+            repository: try!(opt_string(package, "repository")),
+            manifest_dir: manifest_dir,
+            wui: wui,
+        })
     }
+}
 
-    pub fn description(&self) -> io::Result<Option<&str>> {
-        // NOTE: this is not included in the output of `read-manifest`.
-        Ok(None)
+/// Looks up an optional string field; `null` counts as absent.
+fn opt_string(obj: &Json, key: &str) -> io::Result<Option<String>> {
+    match obj.find(key) {
+        None => Ok(None),
+        Some(v) if v.is_null() => Ok(None),
+        Some(v) => v.as_string()
+            .map(|s| Some(s.into()))
+            .ok_or_else(|| io_err(format!("manifest field `{}` is not a string", key))),
     }
+}
 
-    pub fn version(&self) -> io::Result<&str> {
-        try!(self.manifest.find_path(&["version"])
-            .ok_or_else(|| io_err("could not find package version in manifest")))
-            .as_string()
-            .ok_or_else(|| io_err("package version is not a string"))
+fn env_var(name: &str) -> Option<String> {
+    match env::var(name) {
+        Ok(ref v) if v.is_empty() => None,
+        Ok(v) => Some(v),
+        Err(_) => None,
     }
 }
 
-fn cargo_read_manifest() -> io::Result<json::Json> {
-    let output = try!(Command::new("cargo")
-        .arg("read-manifest")
+fn cargo_metadata(manifest_path: &PathBuf) -> io::Result<json::Json> {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = try!(Command::new(cargo)
+        .arg("metadata")
+        .arg("--format-version").arg("1")
+        .arg("--no-deps")
+        .arg("--manifest-path").arg(manifest_path)
         .output());
 
     if !output.status.success() {
-        return Err(io_err("error running `cargo read-manifest`"));
+        return Err(io_err(format!("error running `cargo metadata`: {}",
+            String::from_utf8_lossy(&output.stderr).trim())));
     }
 
     match json::Json::from_reader(&mut &*output.stdout) {
@@ -49,3 +307,73 @@ fn cargo_read_manifest() -> io::Result<json::Json> {
         Err(err) => Err(io_err(err)),
     }
 }
+
+#[cfg(test)]
+#[test]
+fn test_from_metadata() {
+    let metadata = Json::from_str(r#"{
+        "packages": [
+            {
+                "name": "other",
+                "version": "1.0.0"
+            },
+            {
+                "name": "hello",
+                "version": "0.2.1",
+                "manifest_path": "/src/hello/Cargo.toml",
+                "description": "Says hello.",
+                "authors": ["A. Person <a@example.com>", "B. Person"],
+                "homepage": null,
+                "license": "MIT",
+                "repository": "https://example.com/hello",
+                "metadata": {
+                    "wui": {
+                        "company": "Example Ltd.",
+                        "original-filename": "hello.exe",
+                        "build-number": "env:HELLO_BUILD"
+                    }
+                }
+            }
+        ]
+    }"#).unwrap();
+
+    let carman = CargoManifest::from_metadata(&metadata, Some("hello".into())).unwrap();
+    assert_eq!(carman.name, "hello");
+    assert_eq!(carman.version, "0.2.1");
+    assert_eq!(carman.description, Some("Says hello.".into()));
+    assert_eq!(carman.authors, vec!["A. Person <a@example.com>", "B. Person"]);
+    assert_eq!(carman.homepage, None);
+    assert_eq!(carman.license, Some("MIT".into()));
+    assert_eq!(carman.repository, Some("https://example.com/hello".into()));
+    assert_eq!(carman.manifest_dir, PathBuf::from("/src/hello"));
+    assert_eq!(carman.wui, WuiMetadata {
+        company: Some("Example Ltd.".into()),
+        copyright: None,
+        trademarks: None,
+        original_filename: Some("hello.exe".into()),
+        build_number: BuildNumber::Env("HELLO_BUILD".into()),
+        private_build: None,
+    });
+
+    let carman = CargoManifest::from_metadata(&metadata, Some("other".into())).unwrap();
+    assert_eq!(carman.description, None);
+    assert_eq!(carman.wui, WuiMetadata::default());
+
+    assert!(CargoManifest::from_metadata(&metadata, None).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_build_number() {
+    assert_eq!(BuildNumber::parse("git").unwrap(), BuildNumber::GitCommitsSinceTag);
+    assert_eq!(BuildNumber::parse("env:BUILD").unwrap(), BuildNumber::Env("BUILD".into()));
+    assert_eq!(BuildNumber::parse("file:build.txt").unwrap(), BuildNumber::File("build.txt".into()));
+    assert!(BuildNumber::parse("env:").is_err());
+    assert!(BuildNumber::parse("svn").is_err());
+
+    assert_eq!(commits_since_tag("v1.2.0-14-g2414721").unwrap(), 14);
+    assert_eq!(commits_since_tag("release-1-0-g2414721").unwrap(), 0);
+    assert!(commits_since_tag("2414721").is_err());
+    assert_eq!(commits_since_tag("v1-70000-g2414721").unwrap_err().to_string(),
+        "build number 70000 from `git describe` is larger than 65535");
+}
@@ -0,0 +1,429 @@
+/*!
+COFF resource object output.
+
+This does the job of `cvtres`: it takes serialised resources and lays them out as a resource directory tree in an object file the linker can consume directly.
+
+The object has two sections.  `.rsrc$01` holds the directory tables, data entries and name strings; `.rsrc$02` holds the resource data itself.  Each data entry's address is fixed up with an image-relative relocation against `.rsrc$02`.
+*/
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use ::io_err;
+use ::res::{ResEntry, ResId};
+use ::util::ByteBuf;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Machine {
+    X86,
+    X64,
+    Arm64,
+}
+
+impl Machine {
+    fn file_machine(&self) -> u16 {
+        use self::Machine::*;
+        match *self {
+            X86 => IMAGE_FILE_MACHINE_I386,
+            X64 => IMAGE_FILE_MACHINE_AMD64,
+            Arm64 => IMAGE_FILE_MACHINE_ARM64,
+        }
+    }
+
+    fn file_characteristics(&self) -> u16 {
+        use self::Machine::*;
+        match *self {
+            X86 => IMAGE_FILE_32BIT_MACHINE,
+            X64 | Arm64 => 0,
+        }
+    }
+
+    fn rel_addr32nb(&self) -> u16 {
+        use self::Machine::*;
+        match *self {
+            X86 => IMAGE_REL_I386_DIR32NB,
+            X64 => IMAGE_REL_AMD64_ADDR32NB,
+            Arm64 => IMAGE_REL_ARM64_ADDR32NB,
+        }
+    }
+}
+
+const IMAGE_FILE_MACHINE_I386: u16 = 0x014c;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+const IMAGE_FILE_32BIT_MACHINE: u16 = 0x0100;
+
+const IMAGE_REL_I386_DIR32NB: u16 = 0x0007;
+const IMAGE_REL_AMD64_ADDR32NB: u16 = 0x0003;
+const IMAGE_REL_ARM64_ADDR32NB: u16 = 0x0002;
+
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x00000040;
+const IMAGE_SCN_MEM_READ: u32 = 0x40000000;
+
+const IMAGE_SYM_ABSOLUTE: u16 = 0xffff;
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+
+const FILE_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const RELOCATION_SIZE: usize = 10;
+const DIRECTORY_SIZE: usize = 16;
+const DIRECTORY_ENTRY_SIZE: usize = 8;
+const DATA_ENTRY_SIZE: usize = 16;
+
+const SUBDIRECTORY_BIT: u32 = 0x80000000;
+const NAME_BIT: u32 = 0x80000000;
+
+/// Index of the `.rsrc$02` section symbol; see `write_coff`.
+const RSRC02_SYMBOL: u32 = 3;
+
+type LangDir<'a> = BTreeMap<u16, &'a ResEntry>;
+type NameDir<'a> = BTreeMap<DirKey<'a>, LangDir<'a>>;
+type TypeDir<'a> = BTreeMap<DirKey<'a>, NameDir<'a>>;
+
+/**
+A resource id as ordered within a directory table.
+
+The loader binary searches named entries by their uppercased UTF-16, so that is the order they have to be written in.  Names differing only in case fall back to comparing the raw strings, purely to keep the order total.
+*/
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct DirKey<'a>(&'a ResId);
+
+impl<'a> Ord for DirKey<'a> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.0, other.0) {
+            (&ResId::Name(ref a), &ResId::Name(ref b)) =>
+                upcase_utf16(a).cmp(&upcase_utf16(b)).then_with(|| a.cmp(b)),
+            (a, b) => a.cmp(b),
+        }
+    }
+}
+
+impl<'a> PartialOrd for DirKey<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/**
+Uppercases a string one UTF-16 unit at a time, the way `RtlUpcaseUnicodeChar` does.
+
+Units without a single-unit uppercase form, including surrogates, are left alone.
+*/
+fn upcase_utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16()
+        .map(|u| {
+            let mut up = match ::std::char::from_u32(u as u32) {
+                Some(c) => c.to_uppercase(),
+                None => return u,
+            };
+            match (up.next(), up.next()) {
+                (Some(c), None) if (c as u32) < 0x10000 => c as u32 as u16,
+                _ => u,
+            }
+        })
+        .collect()
+}
+
+pub fn write_coff(entries: &[ResEntry], machine: Machine, out: &mut Write) -> io::Result<()> {
+    let mut tree = TypeDir::new();
+    for entry in entries {
+        let langs = tree.entry(DirKey(&entry.type_id)).or_insert_with(NameDir::new)
+            .entry(DirKey(&entry.name_id)).or_insert_with(LangDir::new);
+        if langs.insert(entry.language, entry).is_some() {
+            return Err(io_err(format!(
+                "duplicate resource: type {:?}, name {:?}, language 0x{:04x}",
+                entry.type_id, entry.name_id, entry.language)));
+        }
+    }
+
+    let (rsrc01, relocs, rsrc02) = layout(&tree);
+
+    let rsrc01_at = FILE_HEADER_SIZE + 2*SECTION_HEADER_SIZE;
+    let relocs_at = rsrc01_at + rsrc01.len();
+    let rsrc02_at = relocs_at + relocs.len()*RELOCATION_SIZE;
+    let symbols_at = rsrc02_at + rsrc02.len();
+
+    let mut obj = vec![];
+
+    // File header.
+    obj.push_u16(machine.file_machine());
+    obj.push_u16(2); // sections
+    obj.push_u32(0); // timestamp
+    obj.push_u32(symbols_at as u32);
+    obj.push_u32(5); // symbols
+    obj.push_u16(0); // optional header size
+    obj.push_u16(machine.file_characteristics());
+
+    push_section_header(&mut obj, b".rsrc$01", rsrc01.len(), rsrc01_at,
+        if relocs.len() > 0 { relocs_at } else { 0 }, relocs.len());
+    push_section_header(&mut obj, b".rsrc$02", rsrc02.len(), rsrc02_at, 0, 0);
+
+    obj.extend_from_slice(&rsrc01);
+    for &at in &relocs {
+        obj.push_u32(at as u32);
+        obj.push_u32(RSRC02_SYMBOL);
+        obj.push_u16(machine.rel_addr32nb());
+    }
+    obj.extend_from_slice(&rsrc02);
+
+    /*
+    Symbols.  `@feat.00` marks the object as safe for `/SAFESEH`, which `LINK` insists on for x86; the two section symbols are what the relocations refer to.
+    */
+    push_symbol(&mut obj, b"@feat.00", 0x11, IMAGE_SYM_ABSOLUTE, 0);
+    push_symbol(&mut obj, b".rsrc$01", 0, 1, 1);
+    push_section_aux(&mut obj, rsrc01.len(), relocs.len());
+    push_symbol(&mut obj, b".rsrc$02", 0, 2, 1);
+    push_section_aux(&mut obj, rsrc02.len(), 0);
+
+    // Empty string table.
+    obj.push_u32(4);
+
+    out.write_all(&obj)
+}
+
+/**
+Lays out the contents of both sections.
+
+Returns `.rsrc$01`, the offsets within it that need relocating, and `.rsrc$02`.
+*/
+fn layout(tree: &TypeDir) -> (Vec<u8>, Vec<usize>, Vec<u8>) {
+    fn dir_size(entries: usize) -> usize {
+        DIRECTORY_SIZE + entries*DIRECTORY_ENTRY_SIZE
+    }
+
+    // Directory tables go breadth-first, followed by data entries, then name strings.
+    let mut tables_size = dir_size(tree.len());
+    let mut leaves = 0;
+    for names in tree.values() {
+        tables_size += dir_size(names.len());
+        for langs in names.values() {
+            tables_size += dir_size(langs.len());
+            leaves += langs.len();
+        }
+    }
+    let data_entries_at = tables_size;
+    let strings_at = data_entries_at + leaves*DATA_ENTRY_SIZE;
+
+    let mut strings = vec![];
+    let mut name_offsets = BTreeMap::new();
+    {
+        let mut add_name = |&DirKey(id): &DirKey| {
+            if let ResId::Name(ref s) = *id {
+                if !name_offsets.contains_key(s) {
+                    name_offsets.insert(s.clone(), strings_at + strings.len());
+                    strings.push_u16(s.encode_utf16().count() as u16);
+                    strings.push_wstr(s);
+                }
+            }
+        };
+        for (type_id, names) in tree {
+            add_name(type_id);
+            for name_id in names.keys() {
+                add_name(name_id);
+            }
+        }
+    }
+    let entry_name = |&DirKey(id): &DirKey| match *id {
+        ResId::Name(ref s) => NAME_BIT | name_offsets[s] as u32,
+        ResId::Ordinal(n) => n as u32,
+    };
+
+    let mut rsrc01 = vec![];
+    let mut next_table = dir_size(tree.len());
+
+    push_directory(&mut rsrc01, tree.keys().map(|&DirKey(id)| id));
+    for (type_id, names) in tree {
+        rsrc01.push_u32(entry_name(type_id));
+        rsrc01.push_u32(SUBDIRECTORY_BIT | next_table as u32);
+        next_table += dir_size(names.len());
+    }
+
+    for names in tree.values() {
+        push_directory(&mut rsrc01, names.keys().map(|&DirKey(id)| id));
+        for (name_id, langs) in names {
+            rsrc01.push_u32(entry_name(name_id));
+            rsrc01.push_u32(SUBDIRECTORY_BIT | next_table as u32);
+            next_table += dir_size(langs.len());
+        }
+    }
+
+    let mut next_leaf = data_entries_at;
+    for names in tree.values() {
+        for langs in names.values() {
+            push_directory_header(&mut rsrc01, 0, langs.len());
+            for &lang in langs.keys() {
+                rsrc01.push_u32(lang as u32);
+                rsrc01.push_u32(next_leaf as u32);
+                next_leaf += DATA_ENTRY_SIZE;
+            }
+        }
+    }
+
+    let mut relocs = vec![];
+    let mut rsrc02 = vec![];
+    for names in tree.values() {
+        for langs in names.values() {
+            for entry in langs.values() {
+                rsrc02.align_to(8);
+                relocs.push(rsrc01.len());
+                rsrc01.push_u32(rsrc02.len() as u32);
+                rsrc01.push_u32(entry.data.len() as u32);
+                rsrc01.push_u32(0); // code page
+                rsrc01.push_u32(0); // reserved
+                rsrc02.extend_from_slice(&entry.data);
+            }
+        }
+    }
+
+    rsrc01.extend_from_slice(&strings);
+    rsrc01.align_to(8);
+    rsrc02.align_to(8);
+
+    (rsrc01, relocs, rsrc02)
+}
+
+fn push_directory<'a, It>(buf: &mut Vec<u8>, ids: It)
+where It: Iterator<Item=&'a ResId> {
+    let (mut named, mut numbered) = (0, 0);
+    for id in ids {
+        match *id {
+            ResId::Name(_) => named += 1,
+            ResId::Ordinal(_) => numbered += 1,
+        }
+    }
+    push_directory_header(buf, named, numbered);
+}
+
+fn push_directory_header(buf: &mut Vec<u8>, named: usize, numbered: usize) {
+    buf.push_u32(0); // characteristics
+    buf.push_u32(0); // timestamp
+    buf.push_u16(0); // major version
+    buf.push_u16(0); // minor version
+    buf.push_u16(named as u16);
+    buf.push_u16(numbered as u16);
+}
+
+fn push_section_header(buf: &mut Vec<u8>, name: &[u8; 8], size: usize, at: usize, relocs_at: usize, relocs: usize) {
+    buf.extend_from_slice(name);
+    buf.push_u32(0); // virtual size
+    buf.push_u32(0); // virtual address
+    buf.push_u32(size as u32);
+    buf.push_u32(at as u32);
+    buf.push_u32(relocs_at as u32);
+    buf.push_u32(0); // line numbers
+    buf.push_u16(relocs as u16);
+    buf.push_u16(0); // line numbers
+    buf.push_u32(IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ);
+}
+
+fn push_symbol(buf: &mut Vec<u8>, name: &[u8; 8], value: u32, section: u16, aux: u8) {
+    buf.extend_from_slice(name);
+    buf.push_u32(value);
+    buf.push_u16(section);
+    buf.push_u16(0); // type
+    buf.push(IMAGE_SYM_CLASS_STATIC);
+    buf.push(aux);
+}
+
+fn push_section_aux(buf: &mut Vec<u8>, size: usize, relocs: usize) {
+    buf.push_u32(size as u32);
+    buf.push_u16(relocs as u16);
+    buf.push_u16(0); // line numbers
+    buf.push_u32(0); // checksum
+    buf.push_u16(0); // number
+    buf.push(0); // selection
+    buf.extend_from_slice(&[0; 3]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_coff() {
+    use ::util::{read_u16, read_u32};
+
+    let entries = vec![
+        ResEntry::new(16, 1, b"version"[..].into()),
+        ResEntry::new(24, 1, b"manifest"[..].into()),
+        ResEntry {
+            name_id: ResId::Name("HELLO".into()),
+            ..ResEntry::new(256, 0, b"named"[..].into())
+        },
+        ResEntry {
+            name_id: ResId::Name("apple".into()),
+            ..ResEntry::new(256, 0, b"lower"[..].into())
+        },
+        ResEntry {
+            language: 0x0407,
+            ..ResEntry::new(256, 7, b"de"[..].into())
+        },
+        ResEntry::new(256, 7, b"en"[..].into()),
+    ];
+
+    for &(machine, file_machine, rel_type) in &[
+        (Machine::X86, 0x014c, 0x0007),
+        (Machine::X64, 0x8664, 0x0003),
+        (Machine::Arm64, 0xaa64, 0x0002),
+    ] {
+        let mut obj = vec![];
+        write_coff(&entries, machine, &mut obj).unwrap();
+
+        assert_eq!(read_u16(&obj, 0), file_machine);
+        assert_eq!(read_u16(&obj, 2), 2);
+        assert_eq!(&obj[20..28], b".rsrc$01");
+        assert_eq!(&obj[60..68], b".rsrc$02");
+
+        let rsrc01 = &obj[read_u32(&obj, 20+20) as usize..][..read_u32(&obj, 20+16) as usize];
+        let rsrc02 = &obj[read_u32(&obj, 60+20) as usize..][..read_u32(&obj, 60+16) as usize];
+        let relocs_at = read_u32(&obj, 20+24) as usize;
+        let relocs: Vec<_> = (0..read_u16(&obj, 20+32) as usize)
+            .map(|i| {
+                let at = relocs_at + i*RELOCATION_SIZE;
+                assert_eq!(read_u32(&obj, at+4), RSRC02_SYMBOL);
+                assert_eq!(read_u16(&obj, at+8), rel_type);
+                read_u32(&obj, at) as usize
+            })
+            .collect();
+
+        // Walk the tree, flattening it to (type, name, lang, data).
+        let dir = |at: usize| -> Vec<(u32, u32)> {
+            let n = read_u16(rsrc01, at+12) as usize + read_u16(rsrc01, at+14) as usize;
+            (0..n).map(|i| {
+                let e = at + DIRECTORY_SIZE + i*DIRECTORY_ENTRY_SIZE;
+                (read_u32(rsrc01, e), read_u32(rsrc01, e+4))
+            }).collect()
+        };
+        let id = |name: u32| if name & NAME_BIT != 0 {
+            let at = (name & !NAME_BIT) as usize;
+            let len = read_u16(rsrc01, at) as usize;
+            let s: Vec<u16> = (0..len).map(|i| read_u16(rsrc01, at+2+i*2)).collect();
+            ResId::Name(String::from_utf16(&s).unwrap())
+        } else {
+            ResId::Ordinal(name as u16)
+        };
+
+        let mut found = vec![];
+        for (type_name, type_dir) in dir(0) {
+            assert!(type_dir & SUBDIRECTORY_BIT != 0);
+            for (name, name_dir) in dir((type_dir & !SUBDIRECTORY_BIT) as usize) {
+                assert!(name_dir & SUBDIRECTORY_BIT != 0);
+                for (lang, leaf) in dir((name_dir & !SUBDIRECTORY_BIT) as usize) {
+                    let leaf = leaf as usize;
+                    assert!(relocs.contains(&leaf));
+                    let data_at = read_u32(rsrc01, leaf) as usize;
+                    let data_len = read_u32(rsrc01, leaf+4) as usize;
+                    found.push((id(type_name), id(name), lang as u16,
+                        rsrc02[data_at..data_at+data_len].to_vec()));
+                }
+            }
+        }
+
+        assert_eq!(relocs.len(), entries.len());
+        assert_eq!(found, vec![
+            (ResId::Ordinal(16), ResId::Ordinal(1), 0x0409, b"version".to_vec()),
+            (ResId::Ordinal(24), ResId::Ordinal(1), 0x0409, b"manifest".to_vec()),
+            (ResId::Ordinal(256), ResId::Name("apple".into()), 0x0409, b"lower".to_vec()),
+            (ResId::Ordinal(256), ResId::Name("HELLO".into()), 0x0409, b"named".to_vec()),
+            (ResId::Ordinal(256), ResId::Ordinal(7), 0x0407, b"de".to_vec()),
+            (ResId::Ordinal(256), ResId::Ordinal(7), 0x0409, b"en".to_vec()),
+        ]);
+    }
+}
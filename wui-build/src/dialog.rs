@@ -0,0 +1,58 @@
+/*!
+Dialog box resources.
+
+The template model and its serialization are shared with `wui`, through `wui-template`.
+*/
+use std::fmt;
+use ::res::{ResEntry, MEMFLAG_DISCARDABLE, MEMFLAG_MOVEABLE, MEMFLAG_PURE};
+use ::resource::RT_DIALOG;
+use ::util::write_rc_str;
+
+pub use wui_template::{ControlClass, DialogControl, DialogFont, DialogRect, DialogTemplate, DS_SETFONT};
+
+/// A dialog box template, stored as an `RT_DIALOG` resource.
+pub struct DialogResource {
+    pub name_id: u16,
+    pub template: DialogTemplate,
+}
+
+impl DialogResource {
+    pub fn entry(&self) -> ResEntry {
+        ResEntry {
+            memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_PURE | MEMFLAG_DISCARDABLE,
+            ..ResEntry::new(RT_DIALOG, self.name_id, self.template.to_bytes())
+        }
+    }
+}
+
+impl fmt::Display for DialogResource {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let t = &self.template;
+        try!(writeln!(fmt, "{} DIALOGEX {}", self.name_id, t.rect));
+        try!(writeln!(fmt, "STYLE 0x{:08x}", t.effective_style()));
+        if t.ex_style != 0 {
+            try!(writeln!(fmt, "EXSTYLE 0x{:08x}", t.ex_style));
+        }
+        if !t.caption.is_empty() {
+            try!(write!(fmt, "CAPTION "));
+            try!(write_rc_str(fmt, &t.caption));
+            try!(writeln!(fmt, ""));
+        }
+        if let Some(ref font) = t.font {
+            try!(write!(fmt, "FONT {}, ", font.point_size));
+            try!(write_rc_str(fmt, &font.typeface));
+            try!(writeln!(fmt, ", {}, {}, {}", font.weight, font.italic as u8, font.charset));
+        }
+        try!(writeln!(fmt, "BEGIN"));
+        for control in &t.controls {
+            try!(write!(fmt, "    CONTROL "));
+            try!(write_rc_str(fmt, &control.text));
+            try!(write!(fmt, ", {}, ", control.id));
+            try!(write_rc_str(fmt, control.class.name()));
+            try!(writeln!(fmt, ", 0x{:08x}, {}, 0x{:08x}",
+                control.style, control.rect, control.ex_style));
+        }
+        try!(writeln!(fmt, "END"));
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+/*!
+Parsing of `.ico` and `.cur` files into icon and cursor resources.
+
+Both kinds of file are an `ICONDIR` header followed by a directory of image entries.  Each image becomes its own `RT_ICON` or `RT_CURSOR` resource with an assigned ordinal; the directory is rewritten as an `RT_GROUP_ICON` or `RT_GROUP_CURSOR` resource that refers to those ordinals instead of file offsets.
+
+Animated cursors (`.ani`) are RIFF files which are embedded as-is.
+*/
+use std::io;
+use ::io_err;
+use ::res::{ResEntry, MEMFLAG_DISCARDABLE, MEMFLAG_MOVEABLE, MEMFLAG_PURE};
+use ::resource::{RT_ANICURSOR, RT_CURSOR, RT_GROUP_CURSOR, RT_GROUP_ICON, RT_ICON};
+use ::util::{ByteBuf, read_u16, read_u32};
+
+pub const ICON_FILE_TYPE_ICON: u16 = 1;
+pub const ICON_FILE_TYPE_CURSOR: u16 = 2;
+
+const ICONDIR_SIZE: usize = 6;
+const ICONDIRENTRY_SIZE: usize = 16;
+
+/// One image from an icon file's directory.
+#[derive(Clone, Debug)]
+pub struct IconFileImage {
+    pub width: u8,
+    pub height: u8,
+    pub color_count: u8,
+
+    /// For icons, the number of colour planes.  For cursors, the hotspot's X coordinate.
+    pub planes: u16,
+
+    /// For icons, the bits per pixel.  For cursors, the hotspot's Y coordinate.
+    pub bit_count: u16,
+
+    pub data: Vec<u8>,
+}
+
+impl IconFileImage {
+    /// Works out the colour planes and bits per pixel of an icon image from the image data itself, falling back on `planes` and `bit_count` for PNG images.
+    pub fn image_format(&self) -> (u16, u16) {
+        match bitmap_header(&self.data) {
+            Some(hdr) => (hdr.planes, hdr.bit_count),
+            None => {
+                let planes = if self.planes == 0 { 1 } else { self.planes };
+                let bit_count = if self.bit_count == 0 { 32 } else { self.bit_count };
+                (planes, bit_count)
+            }
+        }
+    }
+
+    /// The cursor hotspot; only meaningful for images from cursor files.
+    pub fn hotspot(&self) -> (u16, u16) {
+        (self.planes, self.bit_count)
+    }
+}
+
+/// The parts of a `BITMAPINFOHEADER` we care about.
+struct BitmapHeader {
+    width: u32,
+    height: u32,
+    planes: u16,
+    bit_count: u16,
+}
+
+/// Reads the header of a bitmap image; returns `None` for PNG images.
+fn bitmap_header(data: &[u8]) -> Option<BitmapHeader> {
+    const PNG_SIGNATURE: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+    if data.starts_with(PNG_SIGNATURE) || data.len() < 16 {
+        None
+    } else {
+        Some(BitmapHeader {
+            width: read_u32(data, 4),
+            height: read_u32(data, 8),
+            planes: read_u16(data, 12),
+            bit_count: read_u16(data, 14),
+        })
+    }
+}
+
+/**
+Parses the contents of an icon or cursor file.
+
+`path` is only used in error messages.  `file_type` should be one of the `ICON_FILE_TYPE_*` constants.
+*/
+pub fn parse_icon_file(path: &str, bytes: &[u8], file_type: u16) -> io::Result<Vec<IconFileImage>> {
+    let bad = |msg: String| io::Error::new(io::ErrorKind::InvalidData,
+        format!("`{}`: {}", path, msg));
+
+    if bytes.len() < ICONDIR_SIZE {
+        return Err(bad(format!("file is too short ({} bytes) to be an icon", bytes.len())));
+    }
+    let reserved = read_u16(bytes, 0);
+    let actual_type = read_u16(bytes, 2);
+    let count = read_u16(bytes, 4) as usize;
+    if reserved != 0 || actual_type != file_type {
+        return Err(bad(format!("bad header (reserved {}, type {}); expected type {}",
+            reserved, actual_type, file_type)));
+    }
+    if count == 0 {
+        return Err(bad("file contains no images".into()));
+    }
+    let dir_end = ICONDIR_SIZE + count*ICONDIRENTRY_SIZE;
+    if bytes.len() < dir_end {
+        return Err(bad(format!("file is too short ({} bytes) for {} directory entries",
+            bytes.len(), count)));
+    }
+
+    let mut images = vec![];
+    for i in 0..count {
+        let at = ICONDIR_SIZE + i*ICONDIRENTRY_SIZE;
+        let size = read_u32(bytes, at + 8) as usize;
+        let offset = read_u32(bytes, at + 12) as usize;
+        if size == 0 {
+            return Err(bad(format!("entry {}: image is empty", i)));
+        }
+        if offset < dir_end || offset > bytes.len() || bytes.len() - offset < size {
+            return Err(bad(format!(
+                "entry {}: image data at offset {} ({} bytes) lies outside the file ({} bytes)",
+                i, offset, size, bytes.len())));
+        }
+        images.push(IconFileImage {
+            width: bytes[at],
+            height: bytes[at + 1],
+            color_count: bytes[at + 2],
+            planes: read_u16(bytes, at + 4),
+            bit_count: read_u16(bytes, at + 6),
+            data: bytes[offset..offset + size].into(),
+        });
+    }
+    Ok(images)
+}
+
+/**
+Turns the images of an icon into `RT_ICON` resources, followed by the `RT_GROUP_ICON` resource that ties them together.
+
+Image ordinals are taken from `next_ordinal`, which is advanced past the ones used.
+*/
+pub fn icon_entries(name_id: u16, images: &[IconFileImage], next_ordinal: &mut u16) -> io::Result<Vec<ResEntry>> {
+    let mut entries = vec![];
+    let mut group = vec![];
+    group.push_u16(0);
+    group.push_u16(ICON_FILE_TYPE_ICON);
+    group.push_u16(images.len() as u16);
+
+    for image in images {
+        let ordinal = try!(take_ordinal(next_ordinal));
+
+        let (planes, bit_count) = image.image_format();
+        group.push(image.width);
+        group.push(image.height);
+        group.push(image.color_count);
+        group.push(0);
+        group.push_u16(planes);
+        group.push_u16(bit_count);
+        group.push_u32(image.data.len() as u32);
+        group.push_u16(ordinal);
+
+        entries.push(ResEntry {
+            memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_DISCARDABLE,
+            ..ResEntry::new(RT_ICON, ordinal, image.data.clone())
+        });
+    }
+
+    entries.push(ResEntry {
+        memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_PURE | MEMFLAG_DISCARDABLE,
+        ..ResEntry::new(RT_GROUP_ICON, name_id, group)
+    });
+    Ok(entries)
+}
+
+/**
+Turns the images of a cursor into `RT_CURSOR` resources, followed by the `RT_GROUP_CURSOR` resource that ties them together.
+
+Unlike icons, each cursor image is prefixed with its hotspot, and the group directory stores full 16-bit dimensions, with the height covering both the colour and mask bitmaps.
+*/
+pub fn cursor_entries(name_id: u16, images: &[IconFileImage], next_ordinal: &mut u16) -> io::Result<Vec<ResEntry>> {
+    let mut entries = vec![];
+    let mut group = vec![];
+    group.push_u16(0);
+    group.push_u16(ICON_FILE_TYPE_CURSOR);
+    group.push_u16(images.len() as u16);
+
+    for image in images {
+        let ordinal = try!(take_ordinal(next_ordinal));
+
+        let (width, height, planes, bit_count) = match bitmap_header(&image.data) {
+            Some(hdr) => (hdr.width as u16, hdr.height as u16, hdr.planes, hdr.bit_count),
+            None => {
+                let width = if image.width == 0 { 256 } else { image.width as u16 };
+                let height = if image.height == 0 { 256 } else { image.height as u16 };
+                (width, height*2, 1, 32)
+            }
+        };
+
+        let (hotspot_x, hotspot_y) = image.hotspot();
+        let mut data = vec![];
+        data.push_u16(hotspot_x);
+        data.push_u16(hotspot_y);
+        data.extend_from_slice(&image.data);
+
+        group.push_u16(width);
+        group.push_u16(height);
+        group.push_u16(planes);
+        group.push_u16(bit_count);
+        group.push_u32(data.len() as u32);
+        group.push_u16(ordinal);
+
+        entries.push(ResEntry {
+            memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_DISCARDABLE,
+            ..ResEntry::new(RT_CURSOR, ordinal, data)
+        });
+    }
+
+    entries.push(ResEntry {
+        memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_PURE | MEMFLAG_DISCARDABLE,
+        ..ResEntry::new(RT_GROUP_CURSOR, name_id, group)
+    });
+    Ok(entries)
+}
+
+/// Returns the next image ordinal, failing once they have all been used.
+fn take_ordinal(next_ordinal: &mut u16) -> io::Result<u16> {
+    let ordinal = *next_ordinal;
+    *next_ordinal = try!(ordinal.checked_add(1)
+        .ok_or_else(|| io_err("too many icon or cursor images: ran out of resource ordinals")));
+    Ok(ordinal)
+}
+
+/// Checks that an animated cursor file looks like one, and turns it into an `RT_ANICURSOR` resource.
+pub fn ani_cursor_entry(path: &str, name_id: u16, bytes: Vec<u8>) -> io::Result<ResEntry> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"ACON" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+            format!("`{}`: not an animated cursor (expected a RIFF ACON file)", path)));
+    }
+    Ok(ResEntry {
+        memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_PURE | MEMFLAG_DISCARDABLE,
+        ..ResEntry::new(RT_ANICURSOR, name_id, bytes)
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_icon_file() {
+    let ico = include_bytes!("../tests/fixtures/two.ico");
+    let images = parse_icon_file("two.ico", ico, ICON_FILE_TYPE_ICON).unwrap();
+    assert_eq!(images.len(), 2);
+    assert_eq!((images[0].width, images[0].height), (1, 1));
+    assert_eq!(images[0].image_format(), (1, 32));
+    assert_eq!(images[1].image_format(), (1, 8));
+
+    let err = parse_icon_file("two.ico", ico, ICON_FILE_TYPE_CURSOR).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("`two.ico`: bad header"));
+
+    let mut truncated = ico[..ico.len() - 1].to_vec();
+    let err = parse_icon_file("two.ico", &truncated, ICON_FILE_TYPE_ICON).unwrap_err();
+    assert!(err.to_string().starts_with("`two.ico`: entry 1: image data at offset"));
+
+    truncated.truncate(20);
+    let err = parse_icon_file("two.ico", &truncated, ICON_FILE_TYPE_ICON).unwrap_err();
+    assert!(err.to_string().contains("too short"));
+
+    let mut next_ordinal = 0xfffe;
+    assert!(icon_entries(1, &images, &mut next_ordinal).is_err());
+}
@@ -0,0 +1,209 @@
+/*!
+Generating Rust constants for resource ids.
+
+Application code pulls the generated module in with:
+
+```ignore
+mod res {
+    include!(concat!(env!("OUT_DIR"), "/wui_resources.rs"));
+}
+```
+
+Each kind of resource gets its own newtype, so an icon id can't be passed where a cursor is expected.  Icons, cursors and accelerator tables implement wui's `AsId`, so they can be given straight to `Icon::load` and friends.
+*/
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::Write;
+use ::io_err;
+use ::resource::ResourceScript;
+
+/// The name of the generated file in `OUT_DIR`.
+pub const ID_MODULE_NAME: &'static str = "wui_resources.rs";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ResourceKind {
+    Icon,
+    Cursor,
+    String,
+    Dialog,
+    Menu,
+    Accelerators,
+}
+
+impl ResourceKind {
+    fn type_name(&self) -> &'static str {
+        use self::ResourceKind::*;
+        match *self {
+            Icon => "IconRes",
+            Cursor => "CursorRes",
+            String => "StringRes",
+            Dialog => "DialogRes",
+            Menu => "MenuRes",
+            Accelerators => "AcceleratorsRes",
+        }
+    }
+
+    /// The prefix for constants that haven't been given a name.
+    fn default_prefix(&self) -> &'static str {
+        use self::ResourceKind::*;
+        match *self {
+            Icon => "ICON",
+            Cursor => "CURSOR",
+            String => "STRING",
+            Dialog => "DIALOG",
+            Menu => "MENU",
+            Accelerators => "ACCELERATORS",
+        }
+    }
+
+    /// The wui id type this kind can be loaded through, if any.
+    fn wui_id(&self) -> Option<&'static str> {
+        use self::ResourceKind::*;
+        match *self {
+            Icon => Some("IconId"),
+            Cursor => Some("CursorId"),
+            Accelerators => Some("AcceleratorsId"),
+            String | Dialog | Menu => None,
+        }
+    }
+}
+
+/**
+Writes a module declaring a constant for every resource in `script`.
+
+Constants are named from `names` where given, and `ICON_1`, `STRING_100` and so on otherwise.
+*/
+pub fn write_id_module(
+    script: &ResourceScript,
+    names: &HashMap<(ResourceKind, u16), String>,
+    out: &mut Write,
+) -> io::Result<()> {
+    use self::ResourceKind::*;
+
+    let mut ids: Vec<(ResourceKind, u16)> = vec![];
+    ids.extend(script.icons.iter().map(|v| (Icon, v.name_id)));
+    ids.extend(script.cursors.iter().map(|v| (Cursor, v.name_id)));
+    ids.extend(script.ani_cursors.iter().map(|v| (Cursor, v.name_id)));
+    for table in script.string_tables.values() {
+        ids.extend(table.strings.keys().map(|&id| (String, id)));
+    }
+    ids.extend(script.dialogs.iter().map(|v| (Dialog, v.name_id)));
+    ids.extend(script.menus.iter().map(|v| (Menu, v.name_id)));
+    ids.extend(script.accelerators.iter().map(|v| (Accelerators, v.name_id)));
+    ids.sort();
+    ids.dedup();
+
+    let mut consts = BTreeMap::new();
+    for &(kind, id) in &ids {
+        let name = match names.get(&(kind, id)) {
+            Some(name) => {
+                if !is_identifier(name) {
+                    return Err(io_err(format!("resource name `{}` is not a valid identifier", name)));
+                }
+                name.clone()
+            },
+            None => format!("{}_{}", kind.default_prefix(), id),
+        };
+        if consts.insert(name.clone(), (kind, id)).is_some() {
+            return Err(io_err(format!("resource name `{}` is used more than once", name)));
+        }
+    }
+
+    try!(writeln!(out, "// Generated by wui-build; do not edit."));
+    let mut kinds: Vec<_> = ids.iter().map(|&(kind, _)| kind).collect();
+    kinds.dedup();
+    for kind in kinds {
+        try!(writeln!(out, ""));
+        try!(writeln!(out, "#[allow(dead_code)]"));
+        try!(writeln!(out, "#[derive(Copy, Clone, Debug, Eq, PartialEq)]"));
+        try!(writeln!(out, "pub struct {}(pub u16);", kind.type_name()));
+        if let Some(wui_id) = kind.wui_id() {
+            try!(writeln!(out, ""));
+            try!(writeln!(out, "impl ::wui::AsId<::wui::{}> for {} {{", wui_id, kind.type_name()));
+            try!(writeln!(out, "    type IdThunk = ::wui::ResourceOrdinal;"));
+            try!(writeln!(out, "    fn into_id_thunk(self) -> Self::IdThunk {{"));
+            try!(writeln!(out, "        ::wui::ResourceOrdinal(self.0)"));
+            try!(writeln!(out, "    }}"));
+            try!(writeln!(out, "}}"));
+        }
+    }
+
+    if !consts.is_empty() {
+        try!(writeln!(out, ""));
+    }
+    for (name, &(kind, id)) in &consts {
+        try!(writeln!(out, "#[allow(dead_code)] pub const {}: {} = {}({});",
+            name, kind.type_name(), kind.type_name(), id));
+    }
+    Ok(())
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => (),
+        _ => return false,
+    }
+    s != "_" && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_id_module() {
+    use ::resource::LCID_EN_US_UTF_16;
+
+    let mut rc = ResourceScript::new();
+    rc.add_icon("app.ico").unwrap();
+    rc.add_icon("doc.ico").unwrap();
+    rc.add_cursor("hand.cur").unwrap();
+    rc.add_string(LCID_EN_US_UTF_16, 100, "Hello");
+    rc.add_string(0x040704B0, 100, "Hallo");
+
+    let mut names = HashMap::new();
+    names.insert((ResourceKind::Icon, 1), "APP_ICON".to_owned());
+    names.insert((ResourceKind::String, 100), "GREETING".to_owned());
+
+    let mut out = vec![];
+    write_id_module(&rc, &names, &mut out).unwrap();
+    assert_eq!(::std::str::from_utf8(&out).unwrap(), "\
+// Generated by wui-build; do not edit.
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct IconRes(pub u16);
+
+impl ::wui::AsId<::wui::IconId> for IconRes {
+    type IdThunk = ::wui::ResourceOrdinal;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        ::wui::ResourceOrdinal(self.0)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CursorRes(pub u16);
+
+impl ::wui::AsId<::wui::CursorId> for CursorRes {
+    type IdThunk = ::wui::ResourceOrdinal;
+    fn into_id_thunk(self) -> Self::IdThunk {
+        ::wui::ResourceOrdinal(self.0)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StringRes(pub u16);
+
+#[allow(dead_code)] pub const APP_ICON: IconRes = IconRes(1);
+#[allow(dead_code)] pub const CURSOR_1: CursorRes = CursorRes(1);
+#[allow(dead_code)] pub const GREETING: StringRes = StringRes(100);
+#[allow(dead_code)] pub const ICON_2: IconRes = IconRes(2);
+");
+
+    names.insert((ResourceKind::Icon, 2), "APP_ICON".to_owned());
+    assert_eq!(write_id_module(&rc, &names, &mut vec![]).unwrap_err().to_string(),
+        "resource name `APP_ICON` is used more than once");
+    names.insert((ResourceKind::Icon, 2), "2ND".to_owned());
+    assert_eq!(write_id_module(&rc, &names, &mut vec![]).unwrap_err().to_string(),
+        "resource name `2ND` is not a valid identifier");
+}
@@ -2,77 +2,47 @@
 extern crate gcc;
 extern crate rustc_serialize;
 extern crate tempfile;
+extern crate wui_template;
+extern crate xml;
 
+pub use accelerator::*;
+pub use builder::*;
 pub use cargo::*;
+pub use coff::*;
+pub use dialog::*;
+pub use icon::*;
+pub use ids::*;
 pub use manifest::*;
+pub use menu::*;
+pub use rc::*;
+pub use res::*;
 pub use resource::*;
 
+mod accelerator;
+mod builder;
 mod cargo;
+mod coff;
+mod dialog;
+mod icon;
+mod ids;
 mod manifest;
+mod menu;
+mod rc;
+mod res;
 mod resource;
+mod util;
 
 use std::env;
-use std::ffi::OsStr;
-use std::fs;
-use std::path::Path;
-use std::process::Command;
-use std::io::{self, Write};
+use std::io;
 
-const RES_OBJ_NAME: &'static str = "wui-generated-resource";
+/**
+Guesses the resources for the package, and compiles them.
 
+This is shorthand for `ResourceBuilder::guess()` followed by `compile()`; use `ResourceBuilder` directly to add or change anything.
+*/
 pub fn guess() -> io::Result<()> {
     println!("wui-build: Guessing resource script...");
-    let mut rc = try!(ResourceScript::guess());
-    let am = try!(guess_manifest(true));
-    rc.user_defined.push(UserDefined {
-        name_id: CREATEPROCESS_MANIFEST_RESOURCE_ID,
-        type_id: RT_MANIFEST,
-        data: UserData::Data(format!("{}", am).into()),
-    });
-
-    let out_dir = try!(env::var("OUT_DIR")
-        .map_err(|_| io_err("could not read OUT_DIR")));
-
-    println!("wui-build: Guessing target...");
-    let target = try!(Target::guess());
-
-    println!("wui-build: Writing resource script...");
-    let rc_path = Path::new(&out_dir)
-        .join(RES_OBJ_NAME).with_extension("rc");
-    let mut rc_file = try!(fs::File::create(&rc_path));
-    try!(rc_file.write_all(format!("{}", rc).as_bytes()));
-    drop(rc_file);
-
-    println!("wui-build: Compiling resource script...");
-    let res_path = rc_path.with_extension(target.res_ext());
-    let mut rc_cmd = try!(target.rc_command(&rc_path, &res_path));
-    let rc_st = try!(rc_cmd.status());
-
-    if !rc_st.success() {
-        return Err(io_err(rc_st.to_string()));
-    }
-
-    /*
-    Cargo is a pain, so we have to be tricky about this.  The fundamental problem is that we can't pass arbitrary objects/options to the linker.  As a result, we have to trick Cargo into letting us link the resource object in.
-
-    For the GNU toolchain, we do this by compiling the resource object into a static library, then link that static library into the final object.
-
-    `LINK`, on the other hand, *will not* carry resources in a static library through to the final output, so we need to pass the resource object file directly to the linker.  Except that, as noted before, Cargo is so dogmatic that *we can't do this*.  The *only* way I could get this to work is to forcibly name the object file `*.dll`, then lie to Cargo that it's a dynamic library *and hope no one notices*.
-    */
-    match target.toolchain {
-        Toolchain::MinGW => {
-            println!("wui-build: Linking resource library...");
-            gcc::Config::new()
-                .object(&res_path)
-                .compile(&format!("lib{}.a", RES_OBJ_NAME));
-        },
-        Toolchain::Msvc => {
-            println!("cargo:rustc-link-lib=dylib={}", RES_OBJ_NAME);
-            println!("cargo:rustc-link-search=native={}", out_dir);
-        },
-    }
-
-    Ok(())
+    try!(ResourceBuilder::guess()).compile()
 }
 
 pub struct Target {
@@ -82,6 +52,10 @@ pub struct Target {
 
 pub enum Toolchain {
     MinGW,
+
+    /// The LLVM flavour of the GNU toolchain, as used by the `*-gnullvm` targets.
+    GnuLlvm,
+
     Msvc,
 }
 
@@ -89,9 +63,18 @@ impl Target {
     pub fn guess() -> io::Result<Self> {
         let target = try!(env::var("TARGET")
             .map_err(|_| io_err("could not read TARGET")));
+        Target::from_triple(&target)
+    }
+
+    pub fn from_triple(target: &str) -> io::Result<Self> {
+        if !target.contains("-windows-") {
+            return Err(io_err(format!("`{}` is not a Windows target", target)));
+        }
 
         let toolchain = if target.ends_with("-gnu") {
             Toolchain::MinGW
+        } else if target.ends_with("-gnullvm") {
+            Toolchain::GnuLlvm
         } else if target.ends_with("-msvc") {
             Toolchain::Msvc
         } else {
@@ -99,47 +82,41 @@ impl Target {
         };
 
         Ok(Target {
-            target: target,
+            target: target.into(),
             toolchain: toolchain,
         })
     }
 
-    pub fn res_ext(&self) -> &'static str {
-        use self::Toolchain::*;
-        match self.toolchain {
-            MinGW => "o",
+    pub fn machine(&self) -> io::Result<Machine> {
+        match self.arch() {
+            "i386" | "i586" | "i686" => Ok(Machine::X86),
+            "x86_64" => Ok(Machine::X64),
+            "aarch64" => Ok(Machine::Arm64),
+            _ => Err(io_err(format!("unknown machine for target `{}`", self.target))),
+        }
+    }
 
-            // Yes, I know this is wrong.  See above about cargo and `LINK`.
-            Msvc => "lib",
+    /// The `processorArchitecture` to use in the application manifest.
+    pub fn proc_arch(&self) -> io::Result<ProcArch> {
+        match self.arch() {
+            "i386" | "i586" | "i686" => Ok(ProcArch::X86),
+            "x86_64" => Ok(ProcArch::Amd64),
+            "aarch64" => Ok(ProcArch::Arm64),
+            _ => Err(io_err(format!("unknown processor architecture for target `{}`", self.target))),
         }
     }
 
-    pub fn rc_command<Input, Output>(&self, input: Input, output: Output) -> io::Result<Command>
-    where Input: AsRef<OsStr>, Output: AsRef<OsStr> {
+    fn arch(&self) -> &str {
+        self.target.split('-').next().unwrap_or("")
+    }
+
+    pub fn res_ext(&self) -> &'static str {
         use self::Toolchain::*;
         match self.toolchain {
-            MinGW => {
-                let mut cmd = Command::new("windres");
-                cmd.arg("-J").arg("rc")
-                    .arg("-i").arg(input)
-                    .arg("-O").arg("coff")
-                    .arg("-o").arg(output);
-                Ok(cmd)
-            },
-            Msvc => {
-                let tool = gcc::windows_registry::find_tool(
-                    &self.target, "rc.exe");
-                let tool = try!(tool
-                    .ok_or_else(|| io_err(format!(
-                        "could not find `rc` for target `{}`",
-                        self.target))));
-                println!("wui-build: rc: {:?}", tool.path());
-                let mut cmd = tool.to_command();
-                cmd.arg("/nologo")
-                    .arg("/fo").arg(output)
-                    .arg(input);
-                Ok(cmd)
-            },
+            MinGW | GnuLlvm => "o",
+
+            // Yes, I know this is wrong.  See above about cargo and `LINK`.
+            Msvc => "lib",
         }
     }
 }
@@ -148,3 +125,25 @@ fn io_err<E>(error: E) -> ::std::io::Error
 where E: Into<Box<::std::error::Error + Send + Sync>> {
     ::std::io::Error::new(::std::io::ErrorKind::Other, error)
 }
+
+#[cfg(test)]
+#[test]
+fn test_target() {
+    fn target(triple: &str) -> (Machine, ProcArch, &'static str) {
+        let t = Target::from_triple(triple).unwrap();
+        (t.machine().unwrap(), t.proc_arch().unwrap(), t.res_ext())
+    }
+
+    assert_eq!(target("i586-pc-windows-msvc"), (Machine::X86, ProcArch::X86, "lib"));
+    assert_eq!(target("i686-pc-windows-gnu"), (Machine::X86, ProcArch::X86, "o"));
+    assert_eq!(target("x86_64-pc-windows-gnullvm"), (Machine::X64, ProcArch::Amd64, "o"));
+    assert_eq!(target("aarch64-pc-windows-msvc"), (Machine::Arm64, ProcArch::Arm64, "lib"));
+    assert_eq!(target("aarch64-pc-windows-gnullvm"), (Machine::Arm64, ProcArch::Arm64, "o"));
+
+    assert_eq!(Target::from_triple("x86_64-unknown-linux-gnu").err().unwrap().to_string(),
+        "`x86_64-unknown-linux-gnu` is not a Windows target");
+    assert_eq!(Target::from_triple("x86_64-pc-windows-foo").err().unwrap().to_string(),
+        "unknown toolchain for target `x86_64-pc-windows-foo`");
+    assert_eq!(Target::from_triple("thumbv7a-pc-windows-msvc").unwrap().machine().unwrap_err().to_string(),
+        "unknown machine for target `thumbv7a-pc-windows-msvc`");
+}
@@ -2,11 +2,19 @@ use std::borrow::Cow;
 use std::fmt;
 use std::io;
 use std::io::Write;
+use ::{io_err, Target};
 use ::cargo::CargoManifest;
+use ::util::read_file;
 
 type CowStr = Cow<'static, str>;
 
 pub const XMLNS_ASM_V1: &'static str = "urn:schemas-microsoft-com:asm.v1";
+pub const XMLNS_ASM_V3: &'static str = "urn:schemas-microsoft-com:asm.v3";
+pub const XMLNS_COMPATIBILITY_V1: &'static str = "urn:schemas-microsoft-com:compatibility.v1";
+pub const XMLNS_WINDOWS_SETTINGS_2005: &'static str = "http://schemas.microsoft.com/SMI/2005/WindowsSettings";
+pub const XMLNS_WINDOWS_SETTINGS_2016: &'static str = "http://schemas.microsoft.com/SMI/2016/WindowsSettings";
+pub const XMLNS_WINDOWS_SETTINGS_2019: &'static str = "http://schemas.microsoft.com/SMI/2019/WindowsSettings";
+pub const XMLNS_WINDOWS_SETTINGS_2020: &'static str = "http://schemas.microsoft.com/SMI/2020/WindowsSettings";
 
 pub const ASM_COMCTL6: AssemblyIdent = AssemblyIdent {
     name: Cow::Borrowed("Microsoft.Windows.Common-Controls"),
@@ -16,13 +24,107 @@ pub const ASM_COMCTL6: AssemblyIdent = AssemblyIdent {
     public_key: Some(Cow::Borrowed("6595b64144ccf1df")),
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Assembly {
-    identity: AssemblyIdent,
+    identity: Option<AssemblyIdent>,
     dependencies: Cow<'static, [AssemblyIdent]>,
+    execution_level: Option<RequestedExecutionLevel>,
+    supported_os: Vec<SupportedOs>,
+    dpi_aware: Option<DpiAware>,
+    dpi_awareness: Vec<DpiAwareness>,
+    long_path_aware: Option<bool>,
+    active_code_page: Option<ActiveCodePage>,
+    heap_type: Option<HeapType>,
+
+    /// Elements read by `parse_manifest` which the model doesn't cover, written back out as they were: directly inside `assembly`, inside the compatibility `application`, and inside `windowsSettings`.
+    other: Vec<XmlElement>,
+    other_compatibility: Vec<XmlElement>,
+    other_windows_settings: Vec<XmlElement>,
+}
+
+impl Assembly {
+    pub fn new(identity: AssemblyIdent) -> Assembly {
+        Assembly {
+            identity: Some(identity),
+            dependencies: [][..].into(),
+            execution_level: None,
+            supported_os: vec![],
+            dpi_aware: None,
+            dpi_awareness: vec![],
+            long_path_aware: None,
+            active_code_page: None,
+            heap_type: None,
+            other: vec![],
+            other_compatibility: vec![],
+            other_windows_settings: vec![],
+        }
+    }
+
+    pub fn dependency(mut self, dep: AssemblyIdent) -> Self {
+        self.dependencies.to_mut().push(dep);
+        self
+    }
+
+    /// Sets the privileges the application asks for when it starts.
+    pub fn execution_level(mut self, level: ExecutionLevel, ui_access: bool) -> Self {
+        self.execution_level = Some(RequestedExecutionLevel {
+            level: level,
+            ui_access: ui_access,
+        });
+        self
+    }
+
+    /// Declares the application as tested on the given versions of Windows.  Without this, newer versions may apply compatibility shims.
+    pub fn supported_os(mut self, os: &[SupportedOs]) -> Self {
+        self.supported_os = os.to_vec();
+        self
+    }
+
+    /// DPI awareness, as understood by Windows Vista through 8.1.
+    pub fn dpi_aware(mut self, value: DpiAware) -> Self {
+        self.dpi_aware = Some(value);
+        self
+    }
+
+    /**
+    DPI awareness, as understood by Windows 10 1607 and later.
+
+    Windows uses the first mode in the list it recognises, so `&[PerMonitorV2, PerMonitor]` falls back to per-monitor awareness before Windows 10 1703.  When present, this overrides `dpi_aware`.
+    */
+    pub fn dpi_awareness(mut self, modes: &[DpiAwareness]) -> Self {
+        self.dpi_awareness = modes.to_vec();
+        self
+    }
+
+    /// Opts out of the `MAX_PATH` limit on Windows 10 1607 and later, if long paths are also enabled system-wide.
+    pub fn long_path_aware(mut self, value: bool) -> Self {
+        self.long_path_aware = Some(value);
+        self
+    }
+
+    /// Sets the process code page on Windows 10 1903 and later.
+    pub fn active_code_page(mut self, value: ActiveCodePage) -> Self {
+        self.active_code_page = Some(value);
+        self
+    }
+
+    /// Selects the heap implementation on Windows 10 2004 and later.
+    pub fn heap_type(mut self, value: HeapType) -> Self {
+        self.heap_type = Some(value);
+        self
+    }
+
+    fn has_windows_settings(&self) -> bool {
+        self.dpi_aware.is_some()
+            || !self.dpi_awareness.is_empty()
+            || self.long_path_aware.is_some()
+            || self.active_code_page.is_some()
+            || self.heap_type.is_some()
+            || !self.other_windows_settings.is_empty()
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AssemblyIdent {
     name: CowStr,
     version: CowStr,
@@ -31,11 +133,121 @@ pub struct AssemblyIdent {
     public_key: Option<CowStr>,
 }
 
-#[derive(Copy, Clone, Debug)]
+impl AssemblyIdent {
+    pub fn new<Name, Version>(name: Name, version: Version) -> AssemblyIdent
+    where Name: Into<CowStr>, Version: Into<CowStr> {
+        AssemblyIdent {
+            name: name.into(),
+            version: version.into(),
+            proc_arch: None,
+            lang: None,
+            public_key: None,
+        }
+    }
+
+    pub fn proc_arch(self, value: ProcArch) -> Self {
+        AssemblyIdent {
+            proc_arch: Some(value),
+            ..self
+        }
+    }
+
+    pub fn lang<S: Into<CowStr>>(self, value: S) -> Self {
+        AssemblyIdent {
+            lang: Some(value.into()),
+            ..self
+        }
+    }
+
+    pub fn public_key<S: Into<CowStr>>(self, value: S) -> Self {
+        AssemblyIdent {
+            public_key: Some(value.into()),
+            ..self
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ProcArch {
     X86,
     Amd64,
     Ia64,
+    Arm64,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct RequestedExecutionLevel {
+    level: ExecutionLevel,
+    ui_access: bool,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ExecutionLevel {
+    AsInvoker,
+    RequireAdministrator,
+    HighestAvailable,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SupportedOs {
+    WindowsVista,
+    Windows7,
+    Windows8,
+    Windows81,
+
+    /// Windows 10 and Windows 11, which share an id.
+    Windows10,
+}
+
+impl SupportedOs {
+    pub fn all() -> &'static [SupportedOs] {
+        use self::SupportedOs::*;
+        const ALL: &'static [SupportedOs] = &[
+            WindowsVista, Windows7, Windows8, Windows81, Windows10,
+        ];
+        ALL
+    }
+
+    pub fn guid(&self) -> &'static str {
+        use self::SupportedOs::*;
+        match *self {
+            WindowsVista => "{e2011457-1546-43c5-a5fe-008deee3d3f0}",
+            Windows7 => "{35138b9a-5d96-4fbd-8e2d-a2440225f93a}",
+            Windows8 => "{4a2f28e3-53b9-4441-ba9c-d69d4a4a6e38}",
+            Windows81 => "{1f676c76-80e1-4239-95bb-83d0f6d0da78}",
+            Windows10 => "{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DpiAware {
+    False,
+    True,
+
+    /// Per-monitor aware on Windows 8.1 and later; system aware before that.
+    TruePerMonitor,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DpiAwareness {
+    Unaware,
+    System,
+    PerMonitor,
+    PerMonitorV2,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ActiveCodePage {
+    Utf8,
+
+    /// The system's ANSI code page.
+    Legacy,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HeapType {
+    SegmentHeap,
 }
 
 pub fn quick_manifest(out: &mut Write, use_comctl6: bool) -> io::Result<()> {
@@ -44,30 +256,17 @@ pub fn quick_manifest(out: &mut Write, use_comctl6: bool) -> io::Result<()> {
 }
 
 pub fn guess_manifest(use_comctl6: bool) -> io::Result<Assembly> {
-    use std::env;
-
-    let carman = CargoManifest::new().unwrap();
-    let name = carman.package_name().unwrap().to_owned();
-    let version = env::var("CARGO_PKG_VERSION").expect("CARGO_PKG_VERSION not set");
-    let version = format!("{}.0", version);
-
-    let target = env::var("TARGET").expect("TARGET not set");
-    let proc_arch = if target.starts_with("i686-") {
-        ProcArch::X86
-    } else if target.starts_with("x86_64-") {
-        ProcArch::Amd64
-    } else {
-        panic!("Unrecognised target: `{}`", target);
-    };
+    let carman = try!(CargoManifest::new());
+    manifest_from_cargo(&carman, use_comctl6)
+}
+
+/// Builds an application manifest naming the package, optionally depending on common controls 6.
+pub fn manifest_from_cargo(carman: &CargoManifest, use_comctl6: bool) -> io::Result<Assembly> {
+    let name = carman.name.clone();
+    let version = format!("{}.0", carman.version);
+    let proc_arch = try!(try!(Target::guess()).proc_arch());
 
     Ok(Assembly {
-        identity: AssemblyIdent {
-            name: name.into(),
-            version: version.into(),
-            proc_arch: Some(proc_arch),
-            lang: None,
-            public_key: None,
-        },
         dependencies: {
             if use_comctl6 {
                 const COMCTL6_DEPS: &'static [AssemblyIdent] = &[ASM_COMCTL6];
@@ -76,6 +275,7 @@ pub fn guess_manifest(use_comctl6: bool) -> io::Result<Assembly> {
                 [][..].into()
             }
         },
+        ..Assembly::new(AssemblyIdent::new(name, version).proc_arch(proc_arch))
     })
 }
 
@@ -85,6 +285,371 @@ pub fn write_manifest(assembly: &Assembly, out: &mut Write) -> io::Result<()> {
     Ok(())
 }
 
+/**
+Reads an existing manifest file, such as a hand-written `app.manifest`.
+
+See `parse_manifest` for what is supported.
+*/
+pub fn read_manifest(path: &str) -> io::Result<Assembly> {
+    let bytes = try!(read_file(path));
+    parse_manifest(&bytes)
+        .map_err(|e| io_err(format!("in manifest `{}`: {}", path, e)))
+}
+
+/**
+Parses a manifest into an `Assembly`.
+
+Elements the model doesn't understand directly inside `assembly`, inside the compatibility `application` (such as `maxversiontested`), or inside `windowsSettings` (such as `gdiScaling`) are kept, and written back out by `write_manifest` with their attributes and contents.  Anything unknown elsewhere is reported as an error.  Unknown attributes on known elements are ignored, and namespaces are only checked on the root element.
+*/
+pub fn parse_manifest(xml: &[u8]) -> io::Result<Assembly> {
+    let root = try!(parse_xml(xml));
+    if root.name != "assembly" || root.namespace.as_ref().map(|s| &s[..]) != Some(XMLNS_ASM_V1) {
+        return Err(io_err(format!("root element must be `assembly` in the `{}` namespace", XMLNS_ASM_V1)));
+    }
+
+    let mut asm = Assembly {
+        identity: None,
+        dependencies: [][..].into(),
+        execution_level: None,
+        supported_os: vec![],
+        dpi_aware: None,
+        dpi_awareness: vec![],
+        long_path_aware: None,
+        active_code_page: None,
+        heap_type: None,
+        other: vec![],
+        other_compatibility: vec![],
+        other_windows_settings: vec![],
+    };
+
+    for child in &root.children {
+        match &*child.name {
+            "assemblyIdentity" => {
+                if asm.identity.is_some() {
+                    return Err(duplicate_element(child));
+                }
+                asm.identity = Some(try!(parse_assembly_ident(child)));
+            },
+            "dependency" => {
+                for dep_asm in try!(child.only_children("dependentAssembly")) {
+                    for ident in try!(dep_asm.only_children("assemblyIdentity")) {
+                        let ident = try!(parse_assembly_ident(ident));
+                        if asm.dependencies.iter().any(|d| d.name == ident.name) {
+                            return Err(io_err(format!("duplicate dependency on `{}`", ident.name)));
+                        }
+                        asm.dependencies.to_mut().push(ident);
+                    }
+                }
+            },
+            "trustInfo" => {
+                for security in try!(child.only_children("security")) {
+                    for privs in try!(security.only_children("requestedPrivileges")) {
+                        for rel in try!(privs.only_children("requestedExecutionLevel")) {
+                            if asm.execution_level.is_some() {
+                                return Err(duplicate_element(rel));
+                            }
+                            asm.execution_level = Some(try!(parse_execution_level(rel)));
+                        }
+                    }
+                }
+            },
+            "compatibility" => {
+                for app in try!(child.only_children("application")) {
+                    for os in &app.children {
+                        if os.name != "supportedOS" {
+                            asm.other_compatibility.push(os.clone());
+                            continue;
+                        }
+                        let id = try!(os.attr("Id")
+                            .ok_or_else(|| io_err("`supportedOS` is missing `Id`")));
+                        let os = try!(SupportedOs::all().iter()
+                            .find(|os| os.guid().eq_ignore_ascii_case(id))
+                            .ok_or_else(|| io_err(format!("unknown `supportedOS` id `{}`", id))));
+                        if asm.supported_os.contains(os) {
+                            return Err(io_err(format!("duplicate `supportedOS` id `{}`", id)));
+                        }
+                        asm.supported_os.push(*os);
+                    }
+                }
+            },
+            "application" => {
+                for settings in try!(child.only_children("windowsSettings")) {
+                    try!(parse_windows_settings(settings, &mut asm));
+                }
+            },
+            _ => asm.other.push(child.clone()),
+        }
+    }
+
+    Ok(asm)
+}
+
+fn parse_assembly_ident(elem: &XmlElement) -> io::Result<AssemblyIdent> {
+    let name = try!(elem.attr("name")
+        .ok_or_else(|| io_err("`assemblyIdentity` is missing `name`")));
+    let version = try!(elem.attr("version")
+        .ok_or_else(|| io_err(format!("`assemblyIdentity` for `{}` is missing `version`", name))));
+    let proc_arch = match elem.attr("processorArchitecture") {
+        None | Some("*") => None,
+        Some(arch) => Some(try!(ProcArch::parse(arch))),
+    };
+    Ok(AssemblyIdent {
+        name: name.to_owned().into(),
+        version: version.to_owned().into(),
+        proc_arch: proc_arch,
+        lang: elem.attr("language").map(|s| s.to_owned().into()),
+        public_key: elem.attr("publicKeyToken").map(|s| s.to_owned().into()),
+    })
+}
+
+fn parse_execution_level(elem: &XmlElement) -> io::Result<RequestedExecutionLevel> {
+    let level = match elem.attr("level") {
+        Some("asInvoker") => ExecutionLevel::AsInvoker,
+        Some("requireAdministrator") => ExecutionLevel::RequireAdministrator,
+        Some("highestAvailable") => ExecutionLevel::HighestAvailable,
+        Some(level) => return Err(io_err(format!("unknown execution level `{}`", level))),
+        None => return Err(io_err("`requestedExecutionLevel` is missing `level`")),
+    };
+    let ui_access = match elem.attr("uiAccess") {
+        None => false,
+        Some(v) => try!(parse_bool("uiAccess", v)),
+    };
+    Ok(RequestedExecutionLevel {
+        level: level,
+        ui_access: ui_access,
+    })
+}
+
+fn parse_windows_settings(settings: &XmlElement, asm: &mut Assembly) -> io::Result<()> {
+    for setting in &settings.children {
+        let value = setting.text.trim();
+        let dup = match &*setting.name {
+            "dpiAware" => {
+                let v = match &*value.to_ascii_lowercase() {
+                    "false" => DpiAware::False,
+                    "true" => DpiAware::True,
+                    "true/pm" | "per monitor" => DpiAware::TruePerMonitor,
+                    _ => return Err(io_err(format!("unknown `dpiAware` value `{}`", value))),
+                };
+                asm.dpi_aware.replace(v).is_some()
+            },
+            "dpiAwareness" => {
+                let mut modes = vec![];
+                for mode in value.split(',') {
+                    modes.push(match &*mode.trim().to_ascii_lowercase() {
+                        "unaware" => DpiAwareness::Unaware,
+                        "system" => DpiAwareness::System,
+                        "permonitor" => DpiAwareness::PerMonitor,
+                        "permonitorv2" => DpiAwareness::PerMonitorV2,
+                        _ => return Err(io_err(format!("unknown `dpiAwareness` value `{}`", mode.trim()))),
+                    });
+                }
+                let dup = !asm.dpi_awareness.is_empty();
+                asm.dpi_awareness = modes;
+                dup
+            },
+            "longPathAware" => {
+                let v = try!(parse_bool("longPathAware", value));
+                asm.long_path_aware.replace(v).is_some()
+            },
+            "activeCodePage" => {
+                let v = match value {
+                    "UTF-8" => ActiveCodePage::Utf8,
+                    "Legacy" => ActiveCodePage::Legacy,
+                    _ => return Err(io_err(format!("unsupported `activeCodePage` value `{}`", value))),
+                };
+                asm.active_code_page.replace(v).is_some()
+            },
+            "heapType" => {
+                let v = match value {
+                    "SegmentHeap" => HeapType::SegmentHeap,
+                    _ => return Err(io_err(format!("unknown `heapType` value `{}`", value))),
+                };
+                asm.heap_type.replace(v).is_some()
+            },
+            _ => {
+                asm.other_windows_settings.push(setting.clone());
+                false
+            },
+        };
+        if dup {
+            return Err(duplicate_element(setting));
+        }
+    }
+    Ok(())
+}
+
+fn parse_bool(what: &str, value: &str) -> io::Result<bool> {
+    match &*value.to_ascii_lowercase() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(io_err(format!("`{}` must be `true` or `false`, not `{}`", what, value))),
+    }
+}
+
+impl ProcArch {
+    fn parse(s: &str) -> io::Result<ProcArch> {
+        match &*s.to_ascii_lowercase() {
+            "x86" => Ok(ProcArch::X86),
+            "amd64" => Ok(ProcArch::Amd64),
+            "ia64" => Ok(ProcArch::Ia64),
+            "arm64" => Ok(ProcArch::Arm64),
+            _ => Err(io_err(format!("unknown processorArchitecture `{}`", s))),
+        }
+    }
+}
+
+/**
+Combines a guessed manifest with an existing one.
+
+Anything set in `existing` takes precedence, but where both manifests say something different about the same thing, that is reported as an error: a `processorArchitecture` that doesn't match the target, two different versions of the same dependency, or conflicting settings.
+*/
+pub fn merge_manifest(guessed: Assembly, existing: Assembly) -> io::Result<Assembly> {
+    let identity = match (guessed.identity, existing.identity) {
+        (Some(g), Some(e)) => {
+            match (g.proc_arch, e.proc_arch) {
+                (Some(ga), Some(ea)) if ga != ea => {
+                    return Err(io_err(format!(
+                        "manifest has processorArchitecture `{}`, but the target is `{}`", ea, ga)));
+                },
+                _ => (),
+            }
+            Some(AssemblyIdent {
+                proc_arch: e.proc_arch.or(g.proc_arch),
+                ..e
+            })
+        },
+        (g, e) => e.or(g),
+    };
+
+    // Dependencies only conflict over the version or a concrete architecture; anything left out or `*` matches.
+    let mut dependencies = existing.dependencies.into_owned();
+    for dep in guessed.dependencies.iter() {
+        match dependencies.iter_mut().find(|d| d.name == dep.name) {
+            Some(d) => {
+                if d.version != dep.version {
+                    return Err(io_err(format!(
+                        "conflicting dependencies on `{}`: version `{}` and version `{}`",
+                        dep.name, d.version, dep.version)));
+                }
+                match (d.proc_arch, dep.proc_arch) {
+                    (Some(da), Some(ga)) if da != ga => {
+                        return Err(io_err(format!(
+                            "conflicting dependencies on `{}`: processorArchitecture `{}` and processorArchitecture `{}`",
+                            dep.name, da, ga)));
+                    },
+                    _ => (),
+                }
+                d.proc_arch = d.proc_arch.or(dep.proc_arch);
+                if d.lang.is_none() { d.lang = dep.lang.clone(); }
+                if d.public_key.is_none() { d.public_key = dep.public_key.clone(); }
+            },
+            None => dependencies.push(dep.clone()),
+        }
+    }
+
+    fn pick<T: PartialEq>(what: &str, guessed: Option<T>, existing: Option<T>) -> io::Result<Option<T>> {
+        match (guessed, existing) {
+            (Some(g), Some(e)) => if g == e {
+                Ok(Some(e))
+            } else {
+                Err(io_err(format!("conflicting `{}` settings", what)))
+            },
+            (g, e) => Ok(e.or(g)),
+        }
+    }
+
+    fn non_empty<T>(v: Vec<T>) -> Option<Vec<T>> {
+        if v.is_empty() { None } else { Some(v) }
+    }
+
+    Ok(Assembly {
+        identity: identity,
+        dependencies: dependencies.into(),
+        execution_level: try!(pick("requestedExecutionLevel", guessed.execution_level, existing.execution_level)),
+        supported_os: try!(pick("supportedOS", non_empty(guessed.supported_os), non_empty(existing.supported_os)))
+            .unwrap_or_default(),
+        dpi_aware: try!(pick("dpiAware", guessed.dpi_aware, existing.dpi_aware)),
+        dpi_awareness: try!(pick("dpiAwareness", non_empty(guessed.dpi_awareness), non_empty(existing.dpi_awareness)))
+            .unwrap_or_default(),
+        long_path_aware: try!(pick("longPathAware", guessed.long_path_aware, existing.long_path_aware)),
+        active_code_page: try!(pick("activeCodePage", guessed.active_code_page, existing.active_code_page)),
+        heap_type: try!(pick("heapType", guessed.heap_type, existing.heap_type)),
+        other: existing.other.into_iter().chain(guessed.other).collect(),
+        other_compatibility: existing.other_compatibility.into_iter().chain(guessed.other_compatibility).collect(),
+        other_windows_settings: existing.other_windows_settings.into_iter().chain(guessed.other_windows_settings).collect(),
+    })
+}
+
+/// Just enough of a document tree to walk a manifest, and to write back the parts the model doesn't cover.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct XmlElement {
+    name: String,
+    namespace: Option<String>,
+    attrs: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter()
+            .find(|a| a.0 == name)
+            .map(|a| &a.1[..])
+    }
+
+    /// Returns the children, all of which must be named `name`.
+    fn only_children(&self, name: &str) -> io::Result<&[XmlElement]> {
+        match self.children.iter().find(|c| c.name != name) {
+            Some(c) => Err(unsupported_element(c)),
+            None => Ok(&self.children),
+        }
+    }
+}
+
+fn unsupported_element(elem: &XmlElement) -> io::Error {
+    io_err(format!("unsupported element `<{}>`", elem.name))
+}
+
+fn duplicate_element(elem: &XmlElement) -> io::Error {
+    io_err(format!("duplicate `<{}>` element", elem.name))
+}
+
+fn parse_xml(xml: &[u8]) -> io::Result<XmlElement> {
+    use xml::reader::{EventReader, XmlEvent};
+
+    let mut stack: Vec<XmlElement> = vec![];
+    for event in EventReader::new(xml) {
+        match try!(event.map_err(|e| io_err(e.to_string()))) {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                stack.push(XmlElement {
+                    name: name.local_name,
+                    namespace: name.namespace,
+                    attrs: attributes.into_iter()
+                        .map(|a| (a.name.local_name, a.value))
+                        .collect(),
+                    children: vec![],
+                    text: String::new(),
+                });
+            },
+            XmlEvent::EndElement { .. } => {
+                let elem = stack.pop().expect("unbalanced element");
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(elem),
+                    None => return Ok(elem),
+                }
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(elem) = stack.last_mut() {
+                    elem.text.push_str(&text);
+                }
+            },
+            _ => (),
+        }
+    }
+    Err(io_err("manifest has no root element"))
+}
+
 impl fmt::Display for Assembly {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         use self::xml_escape_attr as xea;
@@ -92,7 +657,9 @@ impl fmt::Display for Assembly {
             "<assembly xmlns='{xmlns}' manifestVersion='1.0'>",
             xmlns = xea(XMLNS_ASM_V1),
         ));
-        try!(write!(fmt, "{}", self.identity));
+        if let Some(ref identity) = self.identity {
+            try!(write!(fmt, "{}", identity));
+        }
         if self.dependencies.len() > 0 {
             try!(write!(fmt, "<dependency>"));
             for dep in &*self.dependencies {
@@ -102,6 +669,60 @@ impl fmt::Display for Assembly {
             }
             try!(write!(fmt, "</dependency>"));
         }
+        if let Some(ref rel) = self.execution_level {
+            try!(write!(fmt,
+                "<trustInfo xmlns='{xmlns}'>\
+                    <security>\
+                        <requestedPrivileges>\
+                            <requestedExecutionLevel level='{level}' uiAccess='{ui_access}'/>\
+                        </requestedPrivileges>\
+                    </security>\
+                </trustInfo>",
+                xmlns = xea(XMLNS_ASM_V3),
+                level = rel.level,
+                ui_access = rel.ui_access,
+            ));
+        }
+        if !self.supported_os.is_empty() || !self.other_compatibility.is_empty() {
+            try!(write!(fmt, "<compatibility xmlns='{}'>", xea(XMLNS_COMPATIBILITY_V1)));
+            try!(write!(fmt, "<application>"));
+            for os in &self.supported_os {
+                try!(write!(fmt, "<supportedOS Id='{}'/>", os.guid()));
+            }
+            for elem in &self.other_compatibility {
+                try!(write_xml_element(fmt, elem, XMLNS_COMPATIBILITY_V1));
+            }
+            try!(write!(fmt, "</application>"));
+            try!(write!(fmt, "</compatibility>"));
+        }
+        if self.has_windows_settings() {
+            try!(write!(fmt, "<application xmlns='{}'>", xea(XMLNS_ASM_V3)));
+            try!(write!(fmt, "<windowsSettings>"));
+            if let Some(dpi_aware) = self.dpi_aware {
+                try!(write_setting(fmt, "dpiAware", XMLNS_WINDOWS_SETTINGS_2005, dpi_aware));
+            }
+            if !self.dpi_awareness.is_empty() {
+                let modes: Vec<_> = self.dpi_awareness.iter().map(|m| m.to_string()).collect();
+                try!(write_setting(fmt, "dpiAwareness", XMLNS_WINDOWS_SETTINGS_2016, modes.join(", ")));
+            }
+            if let Some(long_path_aware) = self.long_path_aware {
+                try!(write_setting(fmt, "longPathAware", XMLNS_WINDOWS_SETTINGS_2016, long_path_aware));
+            }
+            if let Some(acp) = self.active_code_page {
+                try!(write_setting(fmt, "activeCodePage", XMLNS_WINDOWS_SETTINGS_2019, acp));
+            }
+            if let Some(heap_type) = self.heap_type {
+                try!(write_setting(fmt, "heapType", XMLNS_WINDOWS_SETTINGS_2020, heap_type));
+            }
+            for elem in &self.other_windows_settings {
+                try!(write_xml_element(fmt, elem, XMLNS_ASM_V3));
+            }
+            try!(write!(fmt, "</windowsSettings>"));
+            try!(write!(fmt, "</application>"));
+        }
+        for elem in &self.other {
+            try!(write_xml_element(fmt, elem, XMLNS_ASM_V1));
+        }
         try!(write!(fmt, "</assembly>"));
         Ok(())
     }
@@ -122,10 +743,10 @@ impl fmt::Display for AssemblyIdent {
             proc_arch = DisplayOr::some_or(self.proc_arch.as_ref(), "*"),
         ));
         if let Some(ref lang) = self.lang {
-            try!(write!(fmt, " language='{}'", lang));
+            try!(write!(fmt, " language='{}'", xea(lang)));
         }
         if let Some(ref public_key) = self.public_key {
-            try!(write!(fmt, " publicKeyToken='{}'", public_key));
+            try!(write!(fmt, " publicKeyToken='{}'", xea(public_key)));
         }
         try!(write!(fmt, "/>"));
         Ok(())
@@ -139,10 +760,94 @@ impl fmt::Display for ProcArch {
             X86 => fmt.write_str("x86"),
             Amd64 => fmt.write_str("amd64"),
             Ia64 => fmt.write_str("ia64"),
+            Arm64 => fmt.write_str("arm64"),
+        }
+    }
+}
+
+impl fmt::Display for ExecutionLevel {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::ExecutionLevel::*;
+        match *self {
+            AsInvoker => fmt.write_str("asInvoker"),
+            RequireAdministrator => fmt.write_str("requireAdministrator"),
+            HighestAvailable => fmt.write_str("highestAvailable"),
         }
     }
 }
 
+impl fmt::Display for DpiAware {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::DpiAware::*;
+        match *self {
+            False => fmt.write_str("false"),
+            True => fmt.write_str("true"),
+            TruePerMonitor => fmt.write_str("true/pm"),
+        }
+    }
+}
+
+impl fmt::Display for DpiAwareness {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::DpiAwareness::*;
+        match *self {
+            Unaware => fmt.write_str("unaware"),
+            System => fmt.write_str("system"),
+            PerMonitor => fmt.write_str("permonitor"),
+            PerMonitorV2 => fmt.write_str("permonitorv2"),
+        }
+    }
+}
+
+impl fmt::Display for ActiveCodePage {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::ActiveCodePage::*;
+        match *self {
+            Utf8 => fmt.write_str("UTF-8"),
+            Legacy => fmt.write_str("Legacy"),
+        }
+    }
+}
+
+impl fmt::Display for HeapType {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::HeapType::*;
+        match *self {
+            SegmentHeap => fmt.write_str("SegmentHeap"),
+        }
+    }
+}
+
+/// Writes a `windowsSettings` element, each of which lives in the namespace of the Windows version that introduced it.
+fn write_setting<V>(fmt: &mut fmt::Formatter, name: &str, xmlns: &str, value: V) -> fmt::Result
+where V: fmt::Display {
+    write!(fmt, "<{name} xmlns='{xmlns}'>{value}</{name}>",
+        name = name,
+        xmlns = xml_escape_attr(xmlns),
+        value = value,
+    )
+}
+
+/// Writes an element kept by `parse_manifest`, declaring its namespace if it differs from the parent's.
+fn write_xml_element(fmt: &mut fmt::Formatter, elem: &XmlElement, parent_xmlns: &str) -> fmt::Result {
+    try!(write!(fmt, "<{}", elem.name));
+    let xmlns = elem.namespace.as_ref().map(|s| &s[..]).unwrap_or("");
+    if xmlns != parent_xmlns {
+        try!(write!(fmt, " xmlns='{}'", xml_escape_attr(xmlns)));
+    }
+    for &(ref name, ref value) in &elem.attrs {
+        try!(write!(fmt, " {}='{}'", name, xml_escape_attr(value)));
+    }
+    if elem.children.is_empty() && elem.text.is_empty() {
+        return write!(fmt, "/>");
+    }
+    try!(write!(fmt, ">{}", xml_escape_text(&elem.text)));
+    for child in &elem.children {
+        try!(write_xml_element(fmt, child, xmlns));
+    }
+    write!(fmt, "</{}>", elem.name)
+}
+
 enum DisplayOr<A: fmt::Display, B: fmt::Display> {
     Some(A),
     None(B),
@@ -194,6 +899,14 @@ fn xml_escape_attr(s: &str) -> Cow<str> {
     }
 }
 
+fn xml_escape_text(s: &str) -> Cow<str> {
+    if !(s.contains('<') || s.contains('>') || s.contains('&')) {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"))
+    }
+}
+
 #[cfg(test)]
 #[test]
 fn test_xml_escape_attr() {
@@ -204,3 +917,170 @@ fn test_xml_escape_attr() {
     assert_eq!(xea("ab&cd"), "ab&amp;cd");
     assert_eq!(xea("ab&apos;cd"), "ab&amp;apos;cd");
 }
+
+#[cfg(test)]
+#[test]
+fn test_assembly_display() {
+    let asm = Assembly::new(AssemblyIdent::new("hello", "1.2.3.0").proc_arch(ProcArch::Amd64));
+    assert_eq!(asm.to_string(),
+        "<assembly xmlns='urn:schemas-microsoft-com:asm.v1' manifestVersion='1.0'>\
+            <assemblyIdentity type='win32' name='hello' version='1.2.3.0' processorArchitecture='amd64' />\
+        </assembly>");
+
+    let asm = asm
+        .dependency(ASM_COMCTL6)
+        .execution_level(ExecutionLevel::RequireAdministrator, false)
+        .supported_os(&[SupportedOs::Windows7, SupportedOs::Windows10])
+        .dpi_aware(DpiAware::TruePerMonitor)
+        .dpi_awareness(&[DpiAwareness::PerMonitorV2, DpiAwareness::PerMonitor])
+        .long_path_aware(true)
+        .active_code_page(ActiveCodePage::Utf8)
+        .heap_type(HeapType::SegmentHeap);
+    assert_eq!(asm.to_string(),
+        "<assembly xmlns='urn:schemas-microsoft-com:asm.v1' manifestVersion='1.0'>\
+            <assemblyIdentity type='win32' name='hello' version='1.2.3.0' processorArchitecture='amd64' />\
+            <dependency><dependentAssembly>\
+                <assemblyIdentity type='win32' name='Microsoft.Windows.Common-Controls' version='6.0.0.0' processorArchitecture='*'  publicKeyToken='6595b64144ccf1df'/>\
+            </dependentAssembly></dependency>\
+            <trustInfo xmlns='urn:schemas-microsoft-com:asm.v3'><security><requestedPrivileges>\
+                <requestedExecutionLevel level='requireAdministrator' uiAccess='false'/>\
+            </requestedPrivileges></security></trustInfo>\
+            <compatibility xmlns='urn:schemas-microsoft-com:compatibility.v1'><application>\
+                <supportedOS Id='{35138b9a-5d96-4fbd-8e2d-a2440225f93a}'/>\
+                <supportedOS Id='{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}'/>\
+            </application></compatibility>\
+            <application xmlns='urn:schemas-microsoft-com:asm.v3'><windowsSettings>\
+                <dpiAware xmlns='http://schemas.microsoft.com/SMI/2005/WindowsSettings'>true/pm</dpiAware>\
+                <dpiAwareness xmlns='http://schemas.microsoft.com/SMI/2016/WindowsSettings'>permonitorv2, permonitor</dpiAwareness>\
+                <longPathAware xmlns='http://schemas.microsoft.com/SMI/2016/WindowsSettings'>true</longPathAware>\
+                <activeCodePage xmlns='http://schemas.microsoft.com/SMI/2019/WindowsSettings'>UTF-8</activeCodePage>\
+                <heapType xmlns='http://schemas.microsoft.com/SMI/2020/WindowsSettings'>SegmentHeap</heapType>\
+            </windowsSettings></application>\
+        </assembly>");
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_manifest() {
+    let xml = br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0">
+  <dependency>
+    <dependentAssembly>
+      <assemblyIdentity type="win32" name="Microsoft.Windows.Common-Controls"
+        version="6.0.0.0" processorArchitecture="*" publicKeyToken="6595b64144ccf1df" language="*"/>
+    </dependentAssembly>
+  </dependency>
+  <trustInfo xmlns="urn:schemas-microsoft-com:asm.v2">
+    <security>
+      <requestedPrivileges>
+        <requestedExecutionLevel level="asInvoker" uiAccess="false"/>
+      </requestedPrivileges>
+    </security>
+  </trustInfo>
+  <compatibility xmlns="urn:schemas-microsoft-com:compatibility.v1">
+    <application>
+      <supportedOS Id="{8E0F7A12-BFB3-4FE8-B9A5-48FD50A15A9A}"/>
+    </application>
+  </compatibility>
+  <application xmlns="urn:schemas-microsoft-com:asm.v3">
+    <windowsSettings>
+      <dpiAware xmlns="http://schemas.microsoft.com/SMI/2005/WindowsSettings">true</dpiAware>
+      <dpiAwareness xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">PerMonitorV2,PerMonitor</dpiAwareness>
+    </windowsSettings>
+  </application>
+</assembly>
+"#;
+
+    let parsed = parse_manifest(xml).unwrap();
+    let expected = Assembly {
+        identity: None,
+        ..Assembly::new(ASM_COMCTL6)
+            .dependency(ASM_COMCTL6.lang("*"))
+            .execution_level(ExecutionLevel::AsInvoker, false)
+            .supported_os(&[SupportedOs::Windows10])
+            .dpi_aware(DpiAware::True)
+            .dpi_awareness(&[DpiAwareness::PerMonitorV2, DpiAwareness::PerMonitor])
+    };
+    assert_eq!(parsed, expected);
+
+    // Writing and re-parsing should give back the same manifest.
+    let mut written = vec![];
+    write_manifest(&parsed, &mut written).unwrap();
+    assert_eq!(parse_manifest(&written).unwrap(), parsed);
+
+    // The guessed identity fills in what the file leaves out.
+    let guessed = Assembly::new(AssemblyIdent::new("hello", "0.1.0.0").proc_arch(ProcArch::X86));
+    let merged = merge_manifest(guessed.clone(), parsed.clone()).unwrap();
+    assert_eq!(merged.identity, guessed.identity);
+    assert_eq!(merged.dependencies, parsed.dependencies);
+
+    // The file's `language="*"` dependency on common controls is the same one `guess_manifest(true)` adds.
+    let merged = merge_manifest(guessed.clone().dependency(ASM_COMCTL6), parsed.clone()).unwrap();
+    assert_eq!(merged.dependencies, parsed.dependencies);
+
+    // Elements outside the model are written back out as they were.
+    let xml = br#"<assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0">
+  <description>Tom &amp; Jerry</description>
+  <file name="x.dll"><comClass clsid="{00000000-0000-0000-0000-000000000001}" threadingModel="Apartment"/></file>
+  <compatibility xmlns="urn:schemas-microsoft-com:compatibility.v1">
+    <application>
+      <maxversiontested Id="10.0.18362.1"/>
+    </application>
+  </compatibility>
+  <application xmlns="urn:schemas-microsoft-com:asm.v3">
+    <windowsSettings>
+      <gdiScaling xmlns="http://schemas.microsoft.com/SMI/2017/WindowsSettings">true</gdiScaling>
+    </windowsSettings>
+  </application>
+</assembly>"#;
+    let parsed = parse_manifest(xml).unwrap();
+    assert_eq!(parsed.to_string(),
+        "<assembly xmlns='urn:schemas-microsoft-com:asm.v1' manifestVersion='1.0'>\
+            <compatibility xmlns='urn:schemas-microsoft-com:compatibility.v1'><application>\
+                <maxversiontested Id='10.0.18362.1'/>\
+            </application></compatibility>\
+            <application xmlns='urn:schemas-microsoft-com:asm.v3'><windowsSettings>\
+                <gdiScaling xmlns='http://schemas.microsoft.com/SMI/2017/WindowsSettings'>true</gdiScaling>\
+            </windowsSettings></application>\
+            <description>Tom &amp; Jerry</description>\
+            <file name='x.dll'><comClass clsid='{00000000-0000-0000-0000-000000000001}' threadingModel='Apartment'/></file>\
+        </assembly>");
+    let merged = merge_manifest(guessed.clone(), parsed.clone()).unwrap();
+    assert_eq!(parse_manifest(merged.to_string().as_bytes()).unwrap(), Assembly { identity: merged.identity.clone(), ..parsed });
+
+    // Identity attributes from a file are escaped when written back.
+    let ident = AssemblyIdent::new("hello", "1.0.0.0").lang("a'b").public_key("c&d");
+    assert_eq!(ident.to_string(),
+        "<assemblyIdentity type='win32' name='hello' version='1.0.0.0' processorArchitecture='*'  language='a&apos;b' publicKeyToken='c&amp;d'/>");
+
+    fn err(xml: &str) -> String {
+        parse_manifest(xml.as_bytes()).unwrap_err().to_string()
+    }
+
+    const ASM: &'static str = "<assembly xmlns='urn:schemas-microsoft-com:asm.v1' manifestVersion='1.0'>";
+    assert_eq!(err(&format!("{}<trustInfo><other/></trustInfo></assembly>", ASM)),
+        "unsupported element `<other>`");
+    assert_eq!(err(&format!("{}<dependency><dependentAssembly>\
+            <assemblyIdentity name='A' version='1.0.0.0'/>\
+            <assemblyIdentity name='A' version='2.0.0.0'/>\
+        </dependentAssembly></dependency></assembly>", ASM)),
+        "duplicate dependency on `A`");
+    assert_eq!(err("<assembly manifestVersion='1.0'/>"),
+        "root element must be `assembly` in the `urn:schemas-microsoft-com:asm.v1` namespace");
+
+    fn merge_err(guessed: Assembly, existing: Assembly) -> String {
+        merge_manifest(guessed, existing).unwrap_err().to_string()
+    }
+
+    let existing = Assembly::new(AssemblyIdent::new("hello", "0.1.0.0").proc_arch(ProcArch::Amd64));
+    assert_eq!(merge_err(guessed.clone(), existing),
+        "manifest has processorArchitecture `amd64`, but the target is `x86`");
+    assert_eq!(merge_err(guessed.clone().dependency(ASM_COMCTL6),
+            guessed.clone().dependency(AssemblyIdent::new("Microsoft.Windows.Common-Controls", "5.82.0.0"))),
+        "conflicting dependencies on `Microsoft.Windows.Common-Controls`: version `5.82.0.0` and version `6.0.0.0`");
+    assert_eq!(merge_err(guessed.clone().dependency(ASM_COMCTL6.proc_arch(ProcArch::X86)),
+            guessed.clone().dependency(ASM_COMCTL6.proc_arch(ProcArch::Amd64))),
+        "conflicting dependencies on `Microsoft.Windows.Common-Controls`: processorArchitecture `amd64` and processorArchitecture `x86`");
+    assert_eq!(merge_err(guessed.clone().long_path_aware(false), guessed.clone().long_path_aware(true)),
+        "conflicting `longPathAware` settings");
+}
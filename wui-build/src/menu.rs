@@ -0,0 +1,83 @@
+/*!
+Menu templates.
+
+The template model and its serialization come from `wui-template`, which `wui` also uses to build menus in memory.
+*/
+use std::fmt;
+use ::res::{ResEntry, MEMFLAG_DISCARDABLE, MEMFLAG_MOVEABLE, MEMFLAG_PURE};
+use ::resource::RT_MENU;
+use ::util::write_rc_str;
+
+pub use wui_template::{menu_template_data, MenuItem, MFS_CHECKED, MFS_DEFAULT, MFS_DISABLED, MFT_RADIOCHECK, MFT_SEPARATOR};
+
+/// A menu template, stored as an `RT_MENU` resource.
+pub struct MenuResource {
+    pub name_id: u16,
+    pub items: Vec<MenuItem>,
+}
+
+impl MenuResource {
+    pub fn entry(&self) -> ResEntry {
+        ResEntry {
+            memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_PURE | MEMFLAG_DISCARDABLE,
+            ..ResEntry::new(RT_MENU, self.name_id, menu_template_data(&self.items))
+        }
+    }
+}
+
+impl fmt::Display for MenuResource {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(fmt, "{} MENUEX", self.name_id));
+        write_rc_items(fmt, &self.items, 0)
+    }
+}
+
+fn write_rc_items(fmt: &mut fmt::Formatter, items: &[MenuItem], depth: usize) -> fmt::Result {
+    let indent = "    ".repeat(depth);
+    try!(writeln!(fmt, "{}BEGIN", indent));
+    for item in items {
+        let keyword = match item.sub_menu {
+            Some(_) => "POPUP",
+            None => "MENUITEM",
+        };
+        try!(write!(fmt, "{}    {} ", indent, keyword));
+        try!(write_rc_str(fmt, &item.text));
+        try!(writeln!(fmt, ", {}, 0x{:08x}, 0x{:08x}", item.id, item.item_type, item.state));
+        if let Some(ref sub_menu) = item.sub_menu {
+            try!(write_rc_items(fmt, sub_menu, depth + 1));
+        }
+    }
+    writeln!(fmt, "{}END", indent)
+}
+
+#[cfg(test)]
+#[test]
+fn test_menu_resource() {
+    fn item(text: &str, id: u32, item_type: u32, state: u32) -> MenuItem {
+        MenuItem { text: text.into(), id: id, item_type: item_type, state: state, sub_menu: None }
+    }
+
+    let items = vec![
+        MenuItem {
+            sub_menu: Some(vec![
+                item("O", 100, 0, MFS_DEFAULT),
+                MenuItem::separator(),
+            ]),
+            ..item("F", 0, 0, 0)
+        },
+        item("Hi", 300, MFT_RADIOCHECK, MFS_CHECKED),
+    ];
+
+    let rc = MenuResource { name_id: 2, items: items }.to_string();
+    assert_eq!(rc, "\
+2 MENUEX
+BEGIN
+    POPUP \"F\", 0, 0x00000000, 0x00000000
+    BEGIN
+        MENUITEM \"O\", 100, 0x00000000, 0x00001000
+        MENUITEM \"\", 0, 0x00000800, 0x00000000
+    END
+    MENUITEM \"Hi\", 300, 0x00000200, 0x00000008
+END
+");
+}
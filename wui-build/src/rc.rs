@@ -0,0 +1,336 @@
+/*!
+Finding and running an external resource compiler.
+
+wui-build writes resource objects itself, so this is only used for existing `.rc` files, added with `ResourceBuilder::rc_file`.  Build scripts can also use `RcTool::compile` directly, then link the object in however they like.
+*/
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use gcc;
+use ::{io_err, Target, Toolchain};
+use ::coff::Machine;
+
+/// A resource compiler, able to turn an `.rc` script into an object file.
+#[derive(Clone, Debug)]
+pub enum RcTool {
+    /// GNU `windres` or `llvm-windres`, which produce an object directly.
+    Windres {
+        path: PathBuf,
+
+        /// Passed as `--target`; only needed for unprefixed `llvm-windres`.
+        target: Option<String>,
+    },
+
+    /// Microsoft's `rc`, followed by `cvtres` to turn the `.res` into an object.
+    Msvc {
+        rc: PathBuf,
+        cvtres: PathBuf,
+        env: Vec<(OsString, OsString)>,
+    },
+
+    /// `llvm-rc`, followed by `llvm-cvtres`.
+    LlvmRc {
+        rc: PathBuf,
+        cvtres: PathBuf,
+    },
+}
+
+impl Target {
+    /**
+    Finds a resource compiler for this target.
+
+    The candidates, in order, are:
+
+    1. whatever the `WUI_RC` or `RC` environment variables name, which can be any `windres`, `rc` or `llvm-rc`,
+    2. Visual Studio's `rc`, for MSVC targets on Windows,
+    3. a `windres` prefixed with the MinGW triple for the target, such as `x86_64-w64-mingw32-windres`,
+    4. plain `windres` for GNU targets, or `llvm-windres` for the `gnullvm` targets,
+    5. `llvm-rc` along with `llvm-cvtres`.
+
+    If none of them can be found, the error lists everything that was tried.
+    */
+    pub fn find_rc(&self) -> io::Result<RcTool> {
+        let mut tried = vec![];
+
+        for var in &["WUI_RC", "RC"] {
+            println!("cargo:rerun-if-env-changed={}", var);
+            match env::var_os(var) {
+                Some(ref v) if !v.is_empty() => {
+                    let path = try!(find_in_path(Path::new(v))
+                        .ok_or_else(|| io_err(format!("could not find `{}`, from ${}", Path::new(v).display(), var))));
+                    return RcTool::from_path(&path, self);
+                },
+                _ => tried.push(format!("${} (not set)", var)),
+            }
+        }
+
+        if let Toolchain::Msvc = self.toolchain {
+            let rc = gcc::windows_registry::find_tool(&self.target, "rc.exe");
+            let cvtres = gcc::windows_registry::find_tool(&self.target, "cvtres.exe");
+            if let (Some(rc), Some(cvtres)) = (rc, cvtres) {
+                return Ok(RcTool::Msvc {
+                    rc: rc.path().into(),
+                    cvtres: cvtres.path().into(),
+                    env: rc.env().to_vec(),
+                });
+            }
+            tried.push("Visual Studio `rc` with `cvtres`".into());
+        }
+
+        let prefixed = format!("{}-windres", self.mingw_triple());
+        let mut windres = vec![&prefixed[..]];
+        match self.toolchain {
+            Toolchain::MinGW => windres.push("windres"),
+            Toolchain::GnuLlvm => windres.push("llvm-windres"),
+            Toolchain::Msvc => (),
+        }
+        for name in windres {
+            if let Some(path) = find_in_path(Path::new(name)) {
+                return RcTool::from_path(&path, self);
+            }
+            tried.push(format!("`{}`", name));
+        }
+
+        match (find_in_path(Path::new("llvm-rc")), find_in_path(Path::new("llvm-cvtres"))) {
+            (Some(rc), Some(cvtres)) => return Ok(RcTool::LlvmRc {
+                rc: rc,
+                cvtres: cvtres,
+            }),
+            _ => tried.push("`llvm-rc` with `llvm-cvtres`".into()),
+        }
+
+        Err(io_err(format!("could not find a resource compiler for target `{}`; tried {}",
+            self.target, tried.join(", "))))
+    }
+
+    /// The triple MinGW uses for this target's architecture, which prefixes its tools.
+    fn mingw_triple(&self) -> String {
+        let arch = match self.arch() {
+            "i386" | "i586" | "i686" => "i686",
+            arch => arch,
+        };
+        format!("{}-w64-mingw32", arch)
+    }
+}
+
+impl RcTool {
+    /**
+    Works out what kind of resource compiler `path` is from its name.
+
+    Anything ending in `windres` is taken to be `windres`; `llvm-rc` and `rc` need their matching `cvtres`, which is looked for next to them, then on the `PATH`.
+    */
+    pub fn from_path(path: &Path, target: &Target) -> io::Result<RcTool> {
+        let stem = path.file_stem()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        let find_cvtres = |name: &str| {
+            let sibling = path.with_file_name(name).with_extension(env::consts::EXE_EXTENSION);
+            if sibling.is_file() {
+                Ok(sibling)
+            } else {
+                find_in_path(Path::new(name))
+                    .ok_or_else(|| io_err(format!("could not find `{}` to go with `{}`", name, path.display())))
+            }
+        };
+
+        if stem.ends_with("windres") {
+            Ok(RcTool::Windres {
+                path: path.into(),
+                target: if stem == "llvm-windres" { Some(target.mingw_triple()) } else { None },
+            })
+        } else if stem == "llvm-rc" {
+            Ok(RcTool::LlvmRc {
+                rc: path.into(),
+                cvtres: try!(find_cvtres("llvm-cvtres")),
+            })
+        } else if stem == "rc" {
+            Ok(RcTool::Msvc {
+                rc: path.into(),
+                cvtres: try!(find_cvtres("cvtres")),
+                env: vec![],
+            })
+        } else {
+            Err(io_err(format!("don't know how to use `{}` as a resource compiler", path.display())))
+        }
+    }
+
+    /// Compiles `input` into a COFF object at `output`.
+    pub fn compile(&self, machine: Machine, input: &Path, output: &Path) -> io::Result<()> {
+        let machine_arg = format!("/machine:{}", match machine {
+            Machine::X86 => "x86",
+            Machine::X64 => "x64",
+            Machine::Arm64 => "arm64",
+        });
+        let res = output.with_extension("res");
+
+        match *self {
+            RcTool::Windres { ref path, ref target } => {
+                let mut cmd = Command::new(path);
+                if let Some(ref target) = *target {
+                    cmd.arg(format!("--target={}", target));
+                }
+                cmd.arg("-J").arg("rc")
+                    .arg("-i").arg(input)
+                    .arg("-O").arg("coff")
+                    .arg("-o").arg(output);
+                run(cmd)
+            },
+            RcTool::Msvc { ref rc, ref cvtres, ref env } => {
+                let mut cmd = Command::new(rc);
+                cmd.arg("/nologo")
+                    .arg("/fo").arg(&res)
+                    .arg(input);
+                for var in env {
+                    cmd.env(&var.0, &var.1);
+                }
+                try!(run(cmd));
+
+                let mut cmd = Command::new(cvtres);
+                cmd.arg("/nologo")
+                    .arg(&machine_arg)
+                    .arg(format!("/out:{}", output.display()))
+                    .arg(&res);
+                for var in env {
+                    cmd.env(&var.0, &var.1);
+                }
+                run(cmd)
+            },
+            RcTool::LlvmRc { ref rc, ref cvtres } => {
+                let mut cmd = Command::new(rc);
+                cmd.arg("/fo").arg(&res)
+                    .arg(input);
+                try!(run(cmd));
+
+                let mut cmd = Command::new(cvtres);
+                cmd.arg(&machine_arg)
+                    .arg(format!("/out:{}", output.display()))
+                    .arg(&res);
+                run(cmd)
+            },
+        }
+    }
+}
+
+fn run(mut cmd: Command) -> io::Result<()> {
+    println!("wui-build: running {:?}", cmd);
+    let status = try!(cmd.status()
+        .map_err(|e| io_err(format!("could not run {:?}: {}", cmd, e))));
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io_err(format!("{:?} failed: {}", cmd, status)))
+    }
+}
+
+/// Looks for an executable, either at the given path or, for a bare name, on the `PATH`.
+fn find_in_path(name: &Path) -> Option<PathBuf> {
+    let with_ext = |p: PathBuf| {
+        if p.is_file() {
+            Some(p)
+        } else {
+            let p = p.with_extension(env::consts::EXE_EXTENSION);
+            if p.is_file() { Some(p) } else { None }
+        }
+    };
+
+    if name.components().count() > 1 {
+        return with_ext(name.into());
+    }
+    env::var_os("PATH")
+        .and_then(|paths| env::split_paths(&paths)
+            .filter_map(|dir| with_ext(dir.join(name)))
+            .next())
+}
+
+#[cfg(test)]
+#[test]
+fn test_rc_tool_from_path() {
+    let target = Target::from_triple("aarch64-pc-windows-gnullvm").unwrap();
+    assert_eq!(target.mingw_triple(), "aarch64-w64-mingw32");
+    assert_eq!(Target::from_triple("i586-pc-windows-msvc").unwrap().mingw_triple(),
+        "i686-w64-mingw32");
+
+    match RcTool::from_path(Path::new("/opt/llvm/bin/llvm-windres"), &target).unwrap() {
+        RcTool::Windres { target: Some(ref t), .. } => assert_eq!(t, "aarch64-w64-mingw32"),
+        tool => panic!("unexpected tool {:?}", tool),
+    }
+    match RcTool::from_path(Path::new("/usr/bin/aarch64-w64-mingw32-windres"), &target).unwrap() {
+        RcTool::Windres { target: None, .. } => (),
+        tool => panic!("unexpected tool {:?}", tool),
+    }
+    assert_eq!(RcTool::from_path(Path::new("/usr/bin/gcc"), &target).unwrap_err().to_string(),
+        "don't know how to use `/usr/bin/gcc` as a resource compiler");
+}
+
+#[cfg(test)]
+#[test]
+fn test_find_rc() {
+    use std::fs;
+    use std::process;
+
+    fn describe(tool: io::Result<RcTool>) -> String {
+        let name = |p: &Path| p.file_stem().unwrap().to_string_lossy().into_owned();
+        match tool {
+            Ok(RcTool::Windres { ref path, ref target }) => format!("{} {:?}", name(path), target),
+            Ok(RcTool::Msvc { ref rc, ref cvtres, .. })
+            | Ok(RcTool::LlvmRc { ref rc, ref cvtres }) => format!("{} {}", name(rc), name(cvtres)),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    let dir = env::temp_dir().join(format!("wui-build-test-find-rc-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let stub = |name: &str| {
+        fs::File::create(dir.join(name).with_extension(env::consts::EXE_EXTENSION)).unwrap();
+    };
+
+    // This is the only test that touches these, and nothing else run by the tests uses the `PATH`.
+    let old_path = env::var_os("PATH");
+    env::set_var("PATH", &dir);
+    env::remove_var("WUI_RC");
+    env::remove_var("RC");
+
+    let gnu = Target::from_triple("x86_64-pc-windows-gnu").unwrap();
+    let gnullvm = Target::from_triple("aarch64-pc-windows-gnullvm").unwrap();
+
+    assert_eq!(describe(gnu.find_rc()),
+        "could not find a resource compiler for target `x86_64-pc-windows-gnu`; \
+        tried $WUI_RC (not set), $RC (not set), `x86_64-w64-mingw32-windres`, `windres`, \
+        `llvm-rc` with `llvm-cvtres`");
+    assert_eq!(describe(gnullvm.find_rc()),
+        "could not find a resource compiler for target `aarch64-pc-windows-gnullvm`; \
+        tried $WUI_RC (not set), $RC (not set), `aarch64-w64-mingw32-windres`, `llvm-windres`, \
+        `llvm-rc` with `llvm-cvtres`");
+
+    stub("llvm-rc");
+    assert!(describe(gnu.find_rc()).starts_with("could not find a resource compiler"));
+    stub("llvm-cvtres");
+    assert_eq!(describe(gnu.find_rc()), "llvm-rc llvm-cvtres");
+
+    stub("windres");
+    stub("llvm-windres");
+    assert_eq!(describe(gnu.find_rc()), "windres None");
+    assert_eq!(describe(gnullvm.find_rc()), "llvm-windres Some(\"aarch64-w64-mingw32\")");
+
+    stub("x86_64-w64-mingw32-windres");
+    assert_eq!(describe(gnu.find_rc()), "x86_64-w64-mingw32-windres None");
+
+    env::set_var("RC", "windres");
+    assert_eq!(describe(gnu.find_rc()), "windres None");
+    env::set_var("WUI_RC", dir.join("llvm-rc"));
+    assert_eq!(describe(gnu.find_rc()), "llvm-rc llvm-cvtres");
+    env::set_var("WUI_RC", "missing-rc");
+    assert_eq!(describe(gnu.find_rc()), "could not find `missing-rc`, from $WUI_RC");
+
+    env::remove_var("WUI_RC");
+    env::remove_var("RC");
+    match old_path {
+        Some(path) => env::set_var("PATH", path),
+        None => env::remove_var("PATH"),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,563 @@
+/*!
+Binary resource (`.res`) output.
+
+This serialises resources directly into the 32-bit resource file format produced by `rc` and `windres`, so that no external resource compiler is needed.
+*/
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use ::io_err;
+use ::icon::{ICON_FILE_TYPE_CURSOR, ICON_FILE_TYPE_ICON};
+use ::icon::{ani_cursor_entry, cursor_entries, icon_entries, parse_icon_file};
+use ::resource::*;
+use ::util::{ByteBuf, read_file};
+
+pub const LANG_NEUTRAL: u16 = 0x0000;
+pub const LANG_EN_US: u16 = 0x0409;
+
+pub const MEMFLAG_MOVEABLE: u16 = 0x0010;
+pub const MEMFLAG_PURE: u16 = 0x0020;
+pub const MEMFLAG_PRELOAD: u16 = 0x0040;
+pub const MEMFLAG_DISCARDABLE: u16 = 0x1000;
+
+/**
+A resource type or name.
+
+Names sort before ordinals, which is the order required by resource directories.
+*/
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ResId {
+    Name(String),
+    Ordinal(u16),
+}
+
+/// A single, fully serialised resource.
+#[derive(Clone, Debug)]
+pub struct ResEntry {
+    pub type_id: ResId,
+    pub name_id: ResId,
+    pub language: u16,
+    pub memory_flags: u16,
+    pub data: Vec<u8>,
+}
+
+impl ResEntry {
+    pub fn new(type_id: u16, name_id: u16, data: Vec<u8>) -> ResEntry {
+        ResEntry {
+            type_id: ResId::Ordinal(type_id),
+            name_id: ResId::Ordinal(name_id),
+            language: LANG_EN_US,
+            memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_PURE,
+            data: data,
+        }
+    }
+}
+
+impl ResourceScript {
+    /// Serialises every resource in the script.
+    pub fn entries(&self) -> io::Result<Vec<ResEntry>> {
+        let mut entries = vec![];
+        if let Some(ref vi) = self.version_info {
+            entries.push(ResEntry::new(RT_VERSION, VS_VERSION_INFO as u16,
+                version_info_data(vi)));
+        }
+        let mut next_icon_ordinal = 1;
+        for icon in &self.icons {
+            let bytes = try!(read_file(&icon.path));
+            let images = try!(parse_icon_file(&icon.path, &bytes, ICON_FILE_TYPE_ICON));
+            entries.extend(try!(icon_entries(icon.name_id, &images, &mut next_icon_ordinal)));
+        }
+        let mut next_cursor_ordinal = 1;
+        for cursor in &self.cursors {
+            let bytes = try!(read_file(&cursor.path));
+            let images = try!(parse_icon_file(&cursor.path, &bytes, ICON_FILE_TYPE_CURSOR));
+            entries.extend(try!(cursor_entries(cursor.name_id, &images, &mut next_cursor_ordinal)));
+        }
+        for ani in &self.ani_cursors {
+            let bytes = try!(read_file(&ani.path));
+            entries.push(try!(ani_cursor_entry(&ani.path, ani.name_id, bytes)));
+        }
+        /*
+        Bundles are keyed by language alone, so tables that differ only in code page have to be merged, just as `rc` merges them.
+        */
+        let mut lcids: Vec<_> = self.string_tables.keys().collect();
+        lcids.sort();
+        let mut tables = BTreeMap::new();
+        for &lcid in lcids {
+            let merged = tables.entry(lcid >> 16).or_insert_with(StringTable::new);
+            for (&id, string) in &self.string_tables[&lcid].strings {
+                match merged.strings.insert(id, string.clone()) {
+                    Some(ref prev) if prev != string => return Err(io_err(format!(
+                        "string {} has different text in two string tables for language 0x{:04x}",
+                        id, lcid >> 16))),
+                    _ => (),
+                }
+            }
+        }
+        for (language, table) in tables {
+            entries.extend(string_table_entries(language << 16, &table));
+        }
+        for dialog in &self.dialogs {
+            entries.push(dialog.entry());
+        }
+        for menu in &self.menus {
+            entries.push(menu.entry());
+        }
+        for accels in &self.accelerators {
+            entries.push(accels.entry());
+        }
+        for ud in &self.user_defined {
+            entries.push(ResEntry::new(ud.type_id, ud.name_id,
+                try!(user_data(&ud.data))));
+        }
+        Ok(entries)
+    }
+
+    /// Writes the script out as a binary `.res` file.
+    pub fn write_res(&self, out: &mut Write) -> io::Result<()> {
+        let entries = try!(self.entries());
+        write_res(&entries, out)
+    }
+}
+
+pub fn write_res(entries: &[ResEntry], out: &mut Write) -> io::Result<()> {
+    /*
+    The file starts with an empty entry, which is how tools tell 32-bit resource files apart from 16-bit ones.
+    */
+    let empty = ResEntry {
+        type_id: ResId::Ordinal(0),
+        name_id: ResId::Ordinal(0),
+        language: 0,
+        memory_flags: 0,
+        data: vec![],
+    };
+    try!(write_res_entry(&empty, out));
+    for entry in entries {
+        try!(write_res_entry(entry, out));
+    }
+    Ok(())
+}
+
+fn write_res_entry(entry: &ResEntry, out: &mut Write) -> io::Result<()> {
+    let mut hdr = vec![];
+    hdr.push_u32(entry.data.len() as u32);
+    hdr.push_u32(0); // header size, patched below
+    push_res_id(&mut hdr, &entry.type_id);
+    push_res_id(&mut hdr, &entry.name_id);
+    hdr.align_to(4);
+    hdr.push_u32(0); // data version
+    hdr.push_u16(entry.memory_flags);
+    hdr.push_u16(entry.language);
+    hdr.push_u32(0); // version
+    hdr.push_u32(0); // characteristics
+    let hdr_len = hdr.len() as u32;
+    hdr.set_u32(4, hdr_len);
+
+    try!(out.write_all(&hdr));
+    try!(out.write_all(&entry.data));
+    let pad = (4 - entry.data.len() % 4) % 4;
+    try!(out.write_all(&[0u8; 3][..pad]));
+    Ok(())
+}
+
+fn push_res_id(buf: &mut Vec<u8>, id: &ResId) {
+    match *id {
+        ResId::Ordinal(n) => {
+            buf.push_u16(0xffff);
+            buf.push_u16(n);
+        },
+        ResId::Name(ref s) => buf.push_wstr_null(s),
+    }
+}
+
+/**
+Splits a string table into `RT_STRING` bundles.
+
+String `id` lives in bundle `id / 16 + 1`, at index `id % 16`.  Each bundle holds all 16 slots as length-prefixed UTF-16 strings, with unused slots left empty.
+*/
+pub fn string_table_entries(lcid: LocaleId, table: &StringTable) -> Vec<ResEntry> {
+    let mut bundles = BTreeMap::new();
+    for (&id, string) in &table.strings {
+        let slots = bundles.entry(id / 16).or_insert([None; 16]);
+        slots[(id % 16) as usize] = Some(&string[..]);
+    }
+
+    bundles.into_iter()
+        .map(|(bundle, slots)| {
+            let mut data = vec![];
+            for slot in &slots {
+                let s = slot.unwrap_or("");
+                data.push_u16(s.encode_utf16().count() as u16);
+                data.push_wstr(s);
+            }
+            ResEntry {
+                language: (lcid >> 16) as u16,
+                memory_flags: MEMFLAG_MOVEABLE | MEMFLAG_PURE | MEMFLAG_DISCARDABLE,
+                ..ResEntry::new(RT_STRING, bundle + 1, data)
+            }
+        })
+        .collect()
+}
+
+fn user_data(data: &UserData) -> io::Result<Vec<u8>> {
+    match *data {
+        UserData::Data(ref data) => Ok(data.clone()),
+        UserData::Path(ref path) => read_file(path),
+    }
+}
+
+/**
+Serialises a `VS_VERSIONINFO` tree.
+
+Every node has the same header: total length, value length, value type (0 for binary, 1 for text), and a zero-terminated key.  The value and each child are aligned to a 32-bit boundary; a node's length does not include any padding after its last child.
+*/
+pub fn version_info_data(vi: &VersionInfo) -> Vec<u8> {
+    let mut buf = vec![];
+
+    let mut fixed = vec![];
+    fixed.push_u32(0xfeef04bd); // signature
+    fixed.push_u32(0x00010000); // struct version
+    push_version(&mut fixed, vi.file_version);
+    push_version(&mut fixed, vi.product_version);
+    fixed.push_u32(vi.file_flags_mask);
+    fixed.push_u32(vi.file_flags);
+    fixed.push_u32(vi.file_os);
+    fixed.push_u32(vi.file_type);
+    fixed.push_u32(vi.file_subtype);
+    fixed.push_u32(0); // file date
+    fixed.push_u32(0);
+
+    let mut lcids: Vec<_> = vi.string_file_info.keys().cloned().collect();
+    lcids.sort();
+
+    vi_node(&mut buf, "VS_VERSION_INFO", ViValue::Binary(&fixed), |buf| {
+        vi_node(buf, "StringFileInfo", ViValue::None, |buf| {
+            for &lcid in &lcids {
+                let sfi = &vi.string_file_info[&lcid];
+                let key = format!("{:08X}", lcid);
+                vi_node(buf, &key, ViValue::None, |buf| {
+                    let mut names: Vec<_> = sfi.entries.keys().collect();
+                    names.sort();
+                    for name in names {
+                        vi_node(buf, name.as_str(),
+                            ViValue::Text(&sfi.entries[name]), |_| ());
+                    }
+                });
+            }
+        });
+        vi_node(buf, "VarFileInfo", ViValue::None, |buf| {
+            let mut trans = vec![];
+            for &lcid in &lcids {
+                trans.push_u16((lcid >> 16) as u16);
+                trans.push_u16((lcid & 0xffff) as u16);
+            }
+            vi_node(buf, "Translation", ViValue::Binary(&trans), |_| ());
+        });
+    });
+
+    buf
+}
+
+enum ViValue<'a> {
+    None,
+    Binary(&'a [u8]),
+    Text(&'a str),
+}
+
+fn vi_node<F>(buf: &mut Vec<u8>, key: &str, value: ViValue, children: F)
+where F: FnOnce(&mut Vec<u8>) {
+    buf.align_to(4);
+    let start = buf.len();
+    buf.push_u16(0); // length, patched below
+    match value {
+        ViValue::None => {
+            buf.push_u16(0);
+            buf.push_u16(1);
+        },
+        ViValue::Binary(bytes) => {
+            buf.push_u16(bytes.len() as u16);
+            buf.push_u16(0);
+        },
+        ViValue::Text(s) => {
+            // Text lengths are in `WCHAR`s, including the terminator.
+            buf.push_u16(s.encode_utf16().count() as u16 + 1);
+            buf.push_u16(1);
+        },
+    }
+    buf.push_wstr_null(key);
+    match value {
+        ViValue::None => (),
+        ViValue::Binary(bytes) => {
+            buf.align_to(4);
+            buf.extend_from_slice(bytes);
+        },
+        ViValue::Text(s) => {
+            buf.align_to(4);
+            buf.push_wstr_null(s);
+        },
+    }
+    children(buf);
+    let len = buf.len() - start;
+    buf.set_u16(start, len as u16);
+}
+
+fn push_version(buf: &mut Vec<u8>, ver: Version) {
+    let Version(parts) = ver;
+    buf.push_u32((parts[0] as u32) << 16 | parts[1] as u32);
+    buf.push_u32((parts[2] as u32) << 16 | parts[3] as u32);
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_res() {
+    use self::FileInfoName::*;
+
+    // See `tests/fixtures/version_manifest.rc`.
+    let rc = ResourceScript {
+        version_info: Some(VersionInfo {
+            file_version: Version([1, 2, 3, 0]),
+            product_version: Version([1, 2, 3, 0]),
+            file_flags_mask: 0,
+            file_flags: 0,
+            file_os: VOS_NT,
+            file_type: VFT_APP,
+            file_subtype: VFT2_UNKNOWN,
+            string_file_info: collect![
+                LCID_EN_US_UTF_16 => StringFileInfo {
+                    entries: collect![
+                        ProductName => "demo".into(),
+                        FileVersion => "1.2.3".into(),
+                    ],
+                },
+            ],
+        }),
+        user_defined: vec![
+            UserDefined {
+                name_id: CREATEPROCESS_MANIFEST_RESOURCE_ID,
+                type_id: RT_MANIFEST,
+                data: UserData::Data(b"<a/>"[..].into()),
+            },
+            UserDefined {
+                name_id: 2,
+                type_id: 256,
+                data: UserData::Data(b"odd"[..].into()),
+            },
+        ],
+        ..ResourceScript::new()
+    };
+
+    let mut res = vec![];
+    rc.write_res(&mut res).unwrap();
+    assert_eq!(&res[..], &include_bytes!("../tests/fixtures/version_manifest.res")[..]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_res_icon() {
+    // See `tests/fixtures/icon.rc`.
+    let rc = ResourceScript {
+        icons: vec![
+            IconResource {
+                name_id: 5,
+                path: "tests/fixtures/two.ico".into(),
+            },
+        ],
+        ..ResourceScript::new()
+    };
+
+    let mut res = vec![];
+    rc.write_res(&mut res).unwrap();
+    assert_eq!(&res[..], &include_bytes!("../tests/fixtures/icon.res")[..]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_res_cursor() {
+    // See `tests/fixtures/cursor.rc`.
+    let mut rc = ResourceScript::new();
+    assert_eq!(rc.add_cursor("tests/fixtures/one.cur").unwrap(), 1);
+    rc.cursors[0].name_id = 3;
+
+    let mut res = vec![];
+    rc.write_res(&mut res).unwrap();
+    assert_eq!(&res[..], &include_bytes!("../tests/fixtures/cursor.res")[..]);
+
+    assert_eq!(rc.add_ani_cursor("missing.ani").unwrap(), 4);
+    assert!(rc.entries().is_err());
+
+    rc.ani_cursors[0].name_id = 0xffff;
+    assert!(rc.add_cursor("another.cur").is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_res_ani_cursor() {
+    // `one.ani` is a single-frame animation of `one.cur`.
+    let mut rc = ResourceScript::new();
+    assert_eq!(rc.add_cursor("tests/fixtures/one.cur").unwrap(), 1);
+    assert_eq!(rc.add_ani_cursor("tests/fixtures/one.ani").unwrap(), 2);
+    assert_eq!(rc.add_cursor("tests/fixtures/one.cur").unwrap(), 3);
+
+    let entries = rc.entries().unwrap();
+    let ids: Vec<_> = entries.iter().map(|e| (e.type_id.clone(), e.name_id.clone())).collect();
+    assert_eq!(ids, vec![
+        (ResId::Ordinal(RT_CURSOR), ResId::Ordinal(1)),
+        (ResId::Ordinal(RT_GROUP_CURSOR), ResId::Ordinal(1)),
+        (ResId::Ordinal(RT_CURSOR), ResId::Ordinal(2)),
+        (ResId::Ordinal(RT_GROUP_CURSOR), ResId::Ordinal(3)),
+        (ResId::Ordinal(RT_ANICURSOR), ResId::Ordinal(2)),
+    ]);
+
+    let ani = &entries[4];
+    assert_eq!(ani.language, LANG_EN_US);
+    assert_eq!(ani.memory_flags, MEMFLAG_MOVEABLE | MEMFLAG_PURE | MEMFLAG_DISCARDABLE);
+    assert_eq!(&ani.data[..], &include_bytes!("../tests/fixtures/one.ani")[..]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_res_strings() {
+    const LCID_DE_DE_UTF_16: LocaleId = 0x040704B0;
+
+    // See `tests/fixtures/strings.rc`.
+    let mut rc = ResourceScript::new();
+    rc.add_string(LCID_EN_US_UTF_16, 17, "Say \"hi\" \\ ünï");
+    rc.add_string(LCID_DE_DE_UTF_16, 1, "Hallo");
+    rc.add_string(LCID_EN_US_UTF_16, 1, "Hello");
+
+    let mut res = vec![];
+    rc.write_res(&mut res).unwrap();
+    assert_eq!(&res[..], &include_bytes!("../tests/fixtures/strings.res")[..]);
+
+    // Another code page for the same language merges into the same bundles...
+    let mut merged = ResourceScript::new();
+    merged.add_string(LCID_EN_US_UTF_16, 17, "Say \"hi\" \\ ünï");
+    merged.add_string(LCID_DE_DE_UTF_16, 1, "Hallo");
+    merged.add_string(0x040904E4, 1, "Hello");
+    let mut res = vec![];
+    merged.write_res(&mut res).unwrap();
+    assert_eq!(&res[..], &include_bytes!("../tests/fixtures/strings.res")[..]);
+
+    // ...as long as they agree.
+    merged.add_string(0x040904E4, 17, "Say hi");
+    assert!(merged.entries().is_err());
+
+    let text = rc.to_string();
+    assert!(text.contains("\
+STRINGTABLE LANGUAGE 0x07, 0x01
+BEGIN
+    1, \"Hallo\"
+END
+"));
+    assert!(text.contains("\
+STRINGTABLE LANGUAGE 0x09, 0x01
+BEGIN
+    1, \"Hello\"
+    17, \"Say \"\"hi\"\" \\\\ ünï\"
+END
+"));
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_res_dialog() {
+    use ::dialog::*;
+
+    fn rect(x: i16, y: i16, width: i16, height: i16) -> DialogRect {
+        DialogRect { x: x, y: y, width: width, height: height }
+    }
+
+    // See `tests/fixtures/dialog.rc`.
+    let rc = ResourceScript {
+        dialogs: vec![
+            DialogResource {
+                name_id: 1,
+                template: DialogTemplate {
+                    caption: "About".into(),
+                    font: Some(DialogFont {
+                        point_size: 8,
+                        weight: 400,
+                        italic: false,
+                        charset: 1,
+                        typeface: "MS Shell Dlg".into(),
+                    }),
+                    style: 0x80C80000,
+                    ex_style: 0x00000001,
+                    rect: rect(10, 20, 200, 100),
+                    controls: vec![
+                        DialogControl {
+                            class: ControlClass::Button,
+                            text: "OK".into(),
+                            id: 1,
+                            rect: rect(140, 80, 50, 14),
+                            style: 0x50010001,
+                            ex_style: 0,
+                        },
+                        DialogControl {
+                            class: ControlClass::Static,
+                            text: "Hello \"there\"".into(),
+                            id: 100,
+                            rect: rect(10, 10, 100, 8),
+                            style: 0x50020000,
+                            ex_style: 0x4,
+                        },
+                        DialogControl {
+                            class: ControlClass::Edit,
+                            text: "".into(),
+                            id: 101,
+                            rect: rect(10, 30, 100, 12),
+                            style: 0x50810000,
+                            ex_style: 0,
+                        },
+                        DialogControl {
+                            class: ControlClass::Named("MyClass".into()),
+                            text: "".into(),
+                            id: 102,
+                            rect: rect(10, 50, 100, 8),
+                            style: 0x50000000,
+                            ex_style: 0,
+                        },
+                    ],
+                },
+            },
+        ],
+        ..ResourceScript::new()
+    };
+
+    let mut res = vec![];
+    rc.write_res(&mut res).unwrap();
+    assert_eq!(&res[..], &include_bytes!("../tests/fixtures/dialog.res")[..]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_write_res_accelerators() {
+    use ::accelerator::*;
+
+    // See `tests/fixtures/accelerators.rc`.
+    let rc = ResourceScript {
+        accelerators: vec![
+            AcceleratorResource {
+                name_id: 2,
+                entries: vec![
+                    Accelerator::parse("Ctrl+Shift+S", 101).unwrap(),
+                    Accelerator::parse("F1", 102).unwrap(),
+                    Accelerator::parse("Alt+Delete", 103).unwrap(),
+                ],
+            },
+        ],
+        ..ResourceScript::new()
+    };
+
+    let mut res = vec![];
+    rc.write_res(&mut res).unwrap();
+    assert_eq!(&res[..], &include_bytes!("../tests/fixtures/accelerators.res")[..]);
+
+    assert!(rc.to_string().contains("\
+2 ACCELERATORS
+BEGIN
+    0x53, 101, VIRTKEY, CONTROL, SHIFT
+    0x70, 102, VIRTKEY
+    0x2E, 103, VIRTKEY, ALT
+END
+"));
+}
@@ -2,20 +2,129 @@ use std::collections::HashMap;
 use std::fmt;
 use std::io;
 use ::io_err;
+use ::accelerator::AcceleratorResource;
 use ::cargo::CargoManifest;
+use ::dialog::DialogResource;
+use ::menu::MenuResource;
+use ::util::write_rc_str;
 
 pub struct ResourceScript {
     pub version_info: Option<VersionInfo>,
+    pub icons: Vec<IconResource>,
+    pub cursors: Vec<CursorResource>,
+    pub ani_cursors: Vec<AniCursorResource>,
+    pub string_tables: HashMap<LocaleId, StringTable>,
+    pub dialogs: Vec<DialogResource>,
+    pub menus: Vec<MenuResource>,
+    pub accelerators: Vec<AcceleratorResource>,
     pub user_defined: Vec<UserDefined>,
 }
 
 impl ResourceScript {
+    /// Creates an empty resource script.
+    pub fn new() -> ResourceScript {
+        ResourceScript {
+            version_info: None,
+            icons: vec![],
+            cursors: vec![],
+            ani_cursors: vec![],
+            string_tables: HashMap::new(),
+            dialogs: vec![],
+            menus: vec![],
+            accelerators: vec![],
+            user_defined: vec![],
+        }
+    }
+
     pub fn guess() -> io::Result<ResourceScript> {
+        let carman = try!(CargoManifest::new());
+        ResourceScript::from_manifest(&carman)
+    }
+
+    /// Starts with the version information built from the package's manifest.
+    pub fn from_manifest(carman: &CargoManifest) -> io::Result<ResourceScript> {
         Ok(ResourceScript {
-            version_info: Some(try!(guess_version_info())),
-            user_defined: vec![],
+            version_info: Some(try!(version_info_from_manifest(carman))),
+            ..ResourceScript::new()
         })
     }
+
+    /**
+    Adds a string to the string table for the given locale, replacing any existing string with the same id.
+
+    String resources are only keyed by language, so tables for locales that differ only in code page are merged when compiled; they must not give the same id different text.
+    */
+    pub fn add_string<S: Into<String>>(&mut self, lcid: LocaleId, id: u16, string: S) {
+        self.string_tables.entry(lcid)
+            .or_insert_with(StringTable::new)
+            .strings.insert(id, string.into());
+    }
+
+    /// Adds an icon under the next unused id, and returns that id.
+    pub fn add_icon<P: Into<String>>(&mut self, path: P) -> io::Result<u16> {
+        let name_id = try!(next_id(self.icons.iter().map(|v| v.name_id)));
+        self.icons.push(IconResource {
+            name_id: name_id,
+            path: path.into(),
+        });
+        Ok(name_id)
+    }
+
+    /**
+    Adds a cursor under the next unused id, and returns that id.
+
+    Cursors and animated cursors share ids, since both are loaded through `LoadCursor`.
+    */
+    pub fn add_cursor<P: Into<String>>(&mut self, path: P) -> io::Result<u16> {
+        let name_id = try!(self.next_cursor_id());
+        self.cursors.push(CursorResource {
+            name_id: name_id,
+            path: path.into(),
+        });
+        Ok(name_id)
+    }
+
+    /// Adds an animated cursor under the next unused id, and returns that id.
+    pub fn add_ani_cursor<P: Into<String>>(&mut self, path: P) -> io::Result<u16> {
+        let name_id = try!(self.next_cursor_id());
+        self.ani_cursors.push(AniCursorResource {
+            name_id: name_id,
+            path: path.into(),
+        });
+        Ok(name_id)
+    }
+
+    /// Lists every file that compiling the script will read.
+    pub fn input_paths(&self) -> Vec<&str> {
+        let mut paths: Vec<&str> = vec![];
+        paths.extend(self.icons.iter().map(|v| &v.path[..]));
+        paths.extend(self.cursors.iter().map(|v| &v.path[..]));
+        paths.extend(self.ani_cursors.iter().map(|v| &v.path[..]));
+        for ud in &self.user_defined {
+            if let UserData::Path(ref path) = ud.data {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    fn next_cursor_id(&self) -> io::Result<u16> {
+        next_id(self.cursors.iter().map(|v| v.name_id)
+            .chain(self.ani_cursors.iter().map(|v| v.name_id)))
+    }
+}
+
+fn next_id<It: Iterator<Item=u16>>(ids: It) -> io::Result<u16> {
+    match ids.max() {
+        Some(v) => v.checked_add(1).ok_or_else(|| io_err("ran out of resource ids")),
+        None => Ok(1),
+    }
+}
+
+impl Default for ResourceScript {
+    fn default() -> ResourceScript {
+        ResourceScript::new()
+    }
 }
 
 impl fmt::Display for ResourceScript {
@@ -26,6 +135,35 @@ impl fmt::Display for ResourceScript {
             try!(writeln!(fmt, "1 {}", vi));
             try!(writeln!(fmt, ""));
         }
+        for icon in &self.icons {
+            try!(writeln!(fmt, "{}", icon));
+            try!(writeln!(fmt, ""));
+        }
+        for cursor in &self.cursors {
+            try!(writeln!(fmt, "{}", cursor));
+            try!(writeln!(fmt, ""));
+        }
+        for ani in &self.ani_cursors {
+            try!(writeln!(fmt, "{}", ani));
+            try!(writeln!(fmt, ""));
+        }
+        let mut lcids: Vec<_> = self.string_tables.keys().collect();
+        lcids.sort();
+        for lcid in lcids {
+            let lang = lcid >> 16;
+            try!(writeln!(fmt, "STRINGTABLE LANGUAGE 0x{:02x}, 0x{:02x}",
+                lang & 0x3ff, lang >> 10));
+            try!(writeln!(fmt, "{}", self.string_tables[lcid]));
+        }
+        for dialog in &self.dialogs {
+            try!(writeln!(fmt, "{}", dialog));
+        }
+        for menu in &self.menus {
+            try!(writeln!(fmt, "{}", menu));
+        }
+        for accels in &self.accelerators {
+            try!(writeln!(fmt, "{}", accels));
+        }
         for ud in &self.user_defined {
             try!(writeln!(fmt, "{}", ud));
             try!(writeln!(fmt, ""));
@@ -34,6 +172,91 @@ impl fmt::Display for ResourceScript {
     }
 }
 
+/**
+An icon, loaded from an `.ico` file.
+
+`name_id` identifies the icon group, which is what `LoadIcon` and friends look for.  The individual images are given ordinals automatically.
+*/
+pub struct IconResource {
+    pub name_id: u16,
+    pub path: String,
+}
+
+impl fmt::Display for IconResource {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(fmt, "{} ICON {:?}", self.name_id, self.path));
+        Ok(())
+    }
+}
+
+/**
+A cursor, loaded from a `.cur` file.
+
+As with icons, `name_id` identifies the cursor group; the individual images are given ordinals automatically.
+*/
+pub struct CursorResource {
+    pub name_id: u16,
+    pub path: String,
+}
+
+impl fmt::Display for CursorResource {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(fmt, "{} CURSOR {:?}", self.name_id, self.path));
+        Ok(())
+    }
+}
+
+/// An animated cursor, loaded from an `.ani` file.
+pub struct AniCursorResource {
+    pub name_id: u16,
+    pub path: String,
+}
+
+impl fmt::Display for AniCursorResource {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(fmt, "{} ANICURSOR {:?}", self.name_id, self.path));
+        Ok(())
+    }
+}
+
+/**
+The strings for one locale.
+
+Windows stores these in bundles of 16 consecutive ids, so keeping ids that are used together close to one another keeps the output small.
+*/
+pub struct StringTable {
+    pub strings: HashMap<u16, String>,
+}
+
+impl StringTable {
+    pub fn new() -> StringTable {
+        StringTable {
+            strings: HashMap::new(),
+        }
+    }
+}
+
+impl Default for StringTable {
+    fn default() -> StringTable {
+        StringTable::new()
+    }
+}
+
+impl fmt::Display for StringTable {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut ids: Vec<_> = self.strings.keys().collect();
+        ids.sort();
+        try!(writeln!(fmt, "BEGIN"));
+        for id in ids {
+            try!(write!(fmt, "    {}, ", id));
+            try!(write_rc_str(fmt, &self.strings[id]));
+            try!(writeln!(fmt, ""));
+        }
+        try!(writeln!(fmt, "END"));
+        Ok(())
+    }
+}
+
 pub struct UserDefined {
     pub name_id: u16,
     pub type_id: u16,
@@ -48,6 +271,16 @@ impl fmt::Display for UserDefined {
 }
 
 pub const CREATEPROCESS_MANIFEST_RESOURCE_ID: u16 = 1;
+pub const RT_CURSOR: u16 = 1;
+pub const RT_ICON: u16 = 3;
+pub const RT_MENU: u16 = 4;
+pub const RT_DIALOG: u16 = 5;
+pub const RT_STRING: u16 = 6;
+pub const RT_ACCELERATOR: u16 = 9;
+pub const RT_GROUP_CURSOR: u16 = 12;
+pub const RT_GROUP_ICON: u16 = 14;
+pub const RT_VERSION: u16 = 16;
+pub const RT_ANICURSOR: u16 = 21;
 pub const RT_MANIFEST: u16 = 24;
 
 pub enum UserData {
@@ -137,69 +370,138 @@ impl fmt::Display for StringFileInfo {
 }
 
 pub fn guess_version_info() -> io::Result<VersionInfo> {
+    let carman = try!(CargoManifest::new());
+    version_info_from_manifest(&carman)
+}
+
+/**
+Builds version information from the package's manifest.
+
+Fields with nothing in the manifest to fill them are left out, rather than given placeholder text.  The authors, homepage, repository and license go into `Comments`.
+
+A semver pre-release (`1.0.0-beta.2`) sets `VS_FF_PRERELEASE`.  Build metadata (`1.0.0+nightly`) sets `VS_FF_SPECIALBUILD`, and becomes the `SpecialBuild` string.  `WUI_PRIVATE_BUILD` sets `VS_FF_PRIVATEBUILD` and the `PrivateBuild` string.  The build number comes from `carman.wui.build_number`.
+*/
+pub fn version_info_from_manifest(carman: &CargoManifest) -> io::Result<VersionInfo> {
     use self::FileInfoName::*;
 
-    let carman = try!(CargoManifest::new());
-    let name = try!(carman.package_name());
-    let ver_str = try!(carman.version());
-    let ver = try!(parse_version(ver_str));
-    let desc = try!(carman.description())
-        .unwrap_or("(TODO: guess file description");
+    let pkg_ver = try!(parse_version(&carman.version));
+    let build = try!(carman.wui.build_number.resolve(&carman.manifest_dir));
+    let ver = Version([pkg_ver.major, pkg_ver.minor, pkg_ver.patch, build]);
+
+    let mut file_flags = 0;
+    let mut entries = HashMap::new();
+    entries.insert(FileVersion, carman.version.clone());
+    entries.insert(InternalName, carman.name.clone());
+    entries.insert(ProductName, carman.name.clone());
+    entries.insert(ProductVersion, carman.version.clone());
+
+    if let Some(ref desc) = carman.description {
+        entries.insert(FileDescription, desc.clone());
+    }
+
+    if let Some(ref company) = carman.wui.company {
+        entries.insert(CompanyName, company.clone());
+    }
+
+    if let Some(ref copyright) = carman.wui.copyright {
+        entries.insert(LegalCopyright, copyright.clone());
+    }
+    if let Some(ref trademarks) = carman.wui.trademarks {
+        entries.insert(LegalTrademarks, trademarks.clone());
+    }
+    if let Some(ref filename) = carman.wui.original_filename {
+        entries.insert(OriginalFilename, filename.clone());
+    }
+
+    if pkg_ver.pre_release.is_some() {
+        file_flags |= VS_FF_PRERELEASE;
+    }
+    if let Some(ref private) = carman.wui.private_build {
+        file_flags |= VS_FF_PRIVATEBUILD;
+        entries.insert(PrivateBuild, private.clone());
+    }
+    if let Some(special) = pkg_ver.build_metadata {
+        file_flags |= VS_FF_SPECIALBUILD;
+        entries.insert(SpecialBuild, special);
+    }
+
+    let authors = if carman.authors.is_empty() { None } else { Some(carman.authors.join(", ")) };
+    let comments: Vec<String> = vec![
+            ("Authors", &authors),
+            ("Homepage", &carman.homepage),
+            ("Repository", &carman.repository),
+            ("License", &carman.license),
+        ].into_iter()
+        .filter_map(|(label, value)| value.as_ref().map(|v| format!("{}: {}", label, v)))
+        .collect();
+    if !comments.is_empty() {
+        entries.insert(Comments, comments.join("; "));
+    }
+
     let vi = VersionInfo {
         file_version: ver,
         product_version: ver,
-        file_flags_mask: 0,
-        file_flags: 0,
+        file_flags_mask: VS_FFI_FILEFLAGSMASK,
+        file_flags: file_flags,
         file_os: VOS_NT,
         file_type: VFT_APP,
         file_subtype: VFT2_UNKNOWN,
 
         string_file_info: collect![
             LCID_EN_US_UTF_16 => StringFileInfo {
-                entries: collect![
-                    Comments => "(TODO: guess comments)".into(),
-                    CompanyName => "(TODO: guess company)".into(),
-                    FileDescription => desc.into(),
-                    FileVersion => ver_str.into(),
-                    InternalName => name.into(),
-                    LegalCopyright => "(TODO: guess copyright)".into(),
-                    LegalTrademarks => "(TODO: guess trademarks)".into(),
-                    OriginalFilename => "(TODO: guess filename)".into(),
-                    PrivateBuild => "(TODO: guess private build)".into(),
-                    ProductName => name.into(),
-                    ProductVersion => ver_str.into(),
-                    SpecialBuild => "(TODO: guess special build)".into(),
-                ],
+                entries: entries,
             },
         ],
     };
     Ok(vi)
 }
 
-fn parse_version(ver: &str) -> io::Result<Version> {
-    // TODO: handle "special/internal" versions somehow.
-    let parts = try!(ver.split("-")
-        .next()
-        .ok_or_else(|| io_err("package version is empty")));
-    let mut parts = parts.split(".");
+/// A package version, split into the parts that matter for version information.
+struct PackageVersion {
+    major: u16,
+    minor: u16,
+    patch: u16,
+
+    /// The semver pre-release identifiers, after the `-`.
+    pre_release: Option<String>,
+
+    /// The semver build metadata, after the `+`.
+    build_metadata: Option<String>,
+}
+
+fn parse_version(ver: &str) -> io::Result<PackageVersion> {
+    let (ver, build_metadata) = match ver.find('+') {
+        Some(i) => (&ver[..i], Some(&ver[i + 1..])),
+        None => (ver, None),
+    };
+    let (ver, pre_release) = match ver.find('-') {
+        Some(i) => (&ver[..i], Some(&ver[i + 1..])),
+        None => (ver, None),
+    };
+    if ver.is_empty() {
+        return Err(io_err("package version is empty"));
+    }
+    let mut parts = ver.split('.');
 
     macro_rules! nvc {
         ($what:expr) => {
-            try!(
-                try!(parts.next()
+            {
+                let part = try!(parts.next()
                     .ok_or_else(|| io_err(
                         concat!("package version missing ",
                             $what,
                             " number"))
-                    ))
-                .parse()
-                .map_err(|e| io_err(
-                    format!(
-                        concat!("invalid ", $what, " number: {}"),
-                        e
-                    )
-                ))
-            )
+                    ));
+                match part.parse::<u64>() {
+                    Ok(n) if n <= u16::MAX as u64 => n as u16,
+                    Ok(n) => return Err(io_err(format!(
+                        concat!("package version ", $what, " number {} is larger than {}"),
+                        n, u16::MAX))),
+                    Err(e) => return Err(io_err(format!(
+                        concat!("invalid ", $what, " number `{}`: {}"),
+                        part, e))),
+                }
+            }
         };
     }
 
@@ -207,17 +509,20 @@ fn parse_version(ver: &str) -> io::Result<Version> {
     let min = nvc!("minor");
     let rev = nvc!("revision");
 
-    /*
-    There's really nothing intelligent we can use here without manually maintaining a build number somewhere.
-
-    The components are `u16`s, which isn't enough for a timestamp.
-    */
-    let bld = 0;
+    if parts.next().is_some() {
+        return Err(io_err(format!("package version `{}` has too many components", ver)));
+    }
 
-    Ok(Version([maj, min, rev, bld]))
+    Ok(PackageVersion {
+        major: maj,
+        minor: min,
+        patch: rev,
+        pre_release: pre_release.map(String::from),
+        build_metadata: build_metadata.map(String::from),
+    })
 }
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum FileInfoName {
     Comments,
     CompanyName,
@@ -254,7 +559,7 @@ impl FileInfoName {
 }
 
 #[derive(Copy, Clone)]
-pub struct Version([u16; 4]);
+pub struct Version(pub [u16; 4]);
 
 impl fmt::Display for Version {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -278,6 +583,8 @@ pub const VS_FF_PRIVATEBUILD: u32 = 0x08;
 pub const VS_FF_INFOINFERRED: u32 = 0x10;
 pub const VS_FF_SPECIALBUILD: u32 = 0x20;
 
+pub const VS_FFI_FILEFLAGSMASK: u32 = 0x3F;
+
 pub const VOS_UNKNOWN: u32 = 0x00000000;
 pub const VOS_DOS: u32 = 0x00010000;
 pub const VOS_OS216: u32 = 0x00020000;
@@ -327,3 +634,76 @@ pub const LCID_EN_US_UTF_16: u32 = 0x040904B0;
 pub const LCID_EN_US_WINDOWS_1252: u32 = 0x040904E4;
 
 pub type LocaleId = u32;
+
+#[cfg(test)]
+#[test]
+fn test_version_info_from_manifest() {
+    use self::FileInfoName::*;
+    use ::cargo::WuiMetadata;
+
+    let mut carman = CargoManifest {
+        name: "hello".into(),
+        version: "0.2.1".into(),
+        description: None,
+        authors: vec!["A. Person <a@example.com>".into(), "B. Person".into()],
+        homepage: None,
+        license: Some("MIT".into()),
+        repository: None,
+        manifest_dir: ".".into(),
+        wui: WuiMetadata::default(),
+    };
+
+    let vi = version_info_from_manifest(&carman).unwrap();
+    let entries = &vi.string_file_info[&LCID_EN_US_UTF_16].entries;
+    assert_eq!(entries.get(&ProductName).map(|s| &s[..]), Some("hello"));
+    assert_eq!(entries.get(&FileVersion).map(|s| &s[..]), Some("0.2.1"));
+    assert_eq!(entries.get(&Comments).map(|s| &s[..]), Some("Authors: A. Person <a@example.com>, B. Person; License: MIT"));
+    for name in &[CompanyName, FileDescription, LegalCopyright, LegalTrademarks, OriginalFilename,
+        PrivateBuild, SpecialBuild]
+    {
+        assert!(!entries.contains_key(name));
+    }
+
+    carman.wui.company = Some("Example Ltd.".into());
+    carman.wui.copyright = Some("Copyright 2016 Example Ltd.".into());
+    let vi = version_info_from_manifest(&carman).unwrap();
+    let entries = &vi.string_file_info[&LCID_EN_US_UTF_16].entries;
+    assert_eq!(entries.get(&CompanyName).map(|s| &s[..]), Some("Example Ltd."));
+    assert_eq!(entries.get(&LegalCopyright).map(|s| &s[..]), Some("Copyright 2016 Example Ltd."));
+    assert_eq!(vi.file_flags, 0);
+
+    carman.version = "1.2.3-beta.1+nightly.20160501".into();
+    carman.wui.private_build = Some("Built by A. Person".into());
+    carman.wui.build_number = ::cargo::BuildNumber::Env("WUI_BUILD_TEST_UNSET".into());
+    let vi = version_info_from_manifest(&carman).unwrap();
+    let entries = &vi.string_file_info[&LCID_EN_US_UTF_16].entries;
+    assert_eq!(vi.file_version.0, [1, 2, 3, 0]);
+    assert_eq!(vi.file_flags, VS_FF_PRERELEASE | VS_FF_PRIVATEBUILD | VS_FF_SPECIALBUILD);
+    assert_eq!(entries.get(&PrivateBuild).map(|s| &s[..]), Some("Built by A. Person"));
+    assert_eq!(entries.get(&SpecialBuild).map(|s| &s[..]), Some("nightly.20160501"));
+    assert_eq!(entries.get(&ProductVersion).map(|s| &s[..]), Some("1.2.3-beta.1+nightly.20160501"));
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_version() {
+    let v = parse_version("0.1.0").unwrap();
+    assert_eq!((v.major, v.minor, v.patch), (0, 1, 0));
+    assert_eq!((v.pre_release, v.build_metadata), (None, None));
+
+    let v = parse_version("2.0.1+build-5").unwrap();
+    assert_eq!((v.major, v.minor, v.patch), (2, 0, 1));
+    assert_eq!((v.pre_release, v.build_metadata), (None, Some("build-5".into())));
+
+    let v = parse_version("1.0.0-rc.1").unwrap();
+    assert_eq!((v.pre_release, v.build_metadata), (Some("rc.1".into()), None));
+
+    fn err(s: &str) -> String {
+        parse_version(s).err().unwrap().to_string()
+    }
+
+    assert_eq!(err("1.70000.0"), "package version minor number 70000 is larger than 65535");
+    assert_eq!(err("1.2"), "package version missing revision number");
+    assert_eq!(err("1.x.0"), "invalid minor number `x`: invalid digit found in string");
+    assert_eq!(err("1.2.3.4"), "package version `1.2.3.4` has too many components");
+}
@@ -0,0 +1,94 @@
+/*!
+Helpers for assembling little-endian binary structures, and reading input files.
+*/
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use ::io_err;
+
+pub trait ByteBuf {
+    fn push_u16(&mut self, v: u16);
+    fn push_u32(&mut self, v: u32);
+
+    /// Writes `s` as UTF-16, *without* a terminating zero.
+    fn push_wstr(&mut self, s: &str);
+
+    /// Writes `s` as UTF-16, followed by a terminating zero.
+    fn push_wstr_null(&mut self, s: &str);
+
+    /// Pads with zeroes until the length is a multiple of `align`.
+    fn align_to(&mut self, align: usize);
+
+    fn set_u16(&mut self, at: usize, v: u16);
+    fn set_u32(&mut self, at: usize, v: u32);
+}
+
+impl ByteBuf for Vec<u8> {
+    fn push_u16(&mut self, v: u16) {
+        self.push(v as u8);
+        self.push((v >> 8) as u8);
+    }
+
+    fn push_u32(&mut self, v: u32) {
+        self.push_u16(v as u16);
+        self.push_u16((v >> 16) as u16);
+    }
+
+    fn push_wstr(&mut self, s: &str) {
+        for w in s.encode_utf16() {
+            self.push_u16(w);
+        }
+    }
+
+    fn push_wstr_null(&mut self, s: &str) {
+        self.push_wstr(s);
+        self.push_u16(0);
+    }
+
+    fn align_to(&mut self, align: usize) {
+        let pad = (align - self.len() % align) % align;
+        self.extend((0..pad).map(|_| 0));
+    }
+
+    fn set_u16(&mut self, at: usize, v: u16) {
+        self[at] = v as u8;
+        self[at + 1] = (v >> 8) as u8;
+    }
+
+    fn set_u32(&mut self, at: usize, v: u32) {
+        self.set_u16(at, v as u16);
+        self.set_u16(at + 2, (v >> 16) as u16);
+    }
+}
+
+pub fn read_file(path: &str) -> io::Result<Vec<u8>> {
+    let mut f = try!(fs::File::open(path)
+        .map_err(|e| io_err(format!("could not open `{}`: {}", path, e))));
+    let mut data = vec![];
+    try!(f.read_to_end(&mut data)
+        .map_err(|e| io_err(format!("could not read `{}`: {}", path, e))));
+    Ok(data)
+}
+
+pub fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    bytes[at] as u16 | (bytes[at + 1] as u16) << 8
+}
+
+pub fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    read_u16(bytes, at) as u32 | (read_u16(bytes, at + 2) as u32) << 16
+}
+
+/// Writes `s` as a quoted resource script string literal.
+pub fn write_rc_str(fmt: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    try!(fmt.write_str("\""));
+    for c in s.chars() {
+        match c {
+            '"' => try!(fmt.write_str("\"\"")),
+            '\\' => try!(fmt.write_str("\\\\")),
+            '\n' => try!(fmt.write_str("\\n")),
+            '\t' => try!(fmt.write_str("\\t")),
+            c => try!(write!(fmt, "{}", c)),
+        }
+    }
+    fmt.write_str("\"")
+}
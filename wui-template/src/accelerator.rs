@@ -0,0 +1,162 @@
+/*!
+Keyboard accelerators.
+*/
+use std::fmt;
+use std::io;
+use ::bytes::ByteBuf;
+
+pub const FVIRTKEY: u8 = 0x01;
+pub const FNOINVERT: u8 = 0x02;
+pub const FSHIFT: u8 = 0x04;
+pub const FCONTROL: u8 = 0x08;
+pub const FALT: u8 = 0x10;
+
+/// Marks the last entry in a table.
+const ACCEL_LAST: u16 = 0x80;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Accelerator {
+    /// A combination of the `F*` constants.
+    pub flags: u8,
+
+    /// A virtual-key code if `flags` includes `FVIRTKEY`, otherwise a character code.
+    pub key: u16,
+
+    /// The command id sent in `WM_COMMAND` when the accelerator is pressed.
+    pub id: u16,
+}
+
+impl Accelerator {
+    /**
+    Parses a key combination such as `"Ctrl+Shift+S"` or `"Alt+F4"` into a virtual-key accelerator.
+
+    Modifiers are `Ctrl` (or `Control`), `Shift` and `Alt`, and must come before the key.  The key is a letter, a digit, `F1` to `F24`, or one of the names of the other common keys (`Enter`, `Esc`, `Tab`, `Space`, `Backspace`, `Delete`, `Insert`, `Home`, `End`, `PageUp`, `PageDown`, `Left`, `Up`, `Right`, `Down`, `Plus`, `Minus`).  Everything is case-insensitive.
+    */
+    pub fn parse(s: &str, id: u16) -> io::Result<Accelerator> {
+        let parts: Vec<&str> = s.split('+').map(|p| p.trim()).collect();
+        let (key, modifiers) = parts.split_last().expect("split always yields at least one part");
+
+        let mut flags = FVIRTKEY;
+        for modifier in modifiers {
+            let flag = match &*modifier.to_ascii_lowercase() {
+                "ctrl" | "control" => FCONTROL,
+                "shift" => FSHIFT,
+                "alt" => FALT,
+                "" => return Err(io_err(format!("accelerator `{}`: empty modifier", s))),
+                _ => return Err(io_err(format!("accelerator `{}`: unknown modifier `{}`", s, modifier))),
+            };
+            if flags & flag != 0 {
+                return Err(io_err(format!("accelerator `{}`: modifier `{}` given twice", s, modifier)));
+            }
+            flags |= flag;
+        }
+
+        match virtual_key(key) {
+            Some(key) => Ok(Accelerator {
+                flags: flags,
+                key: key,
+                id: id,
+            }),
+            None if key.is_empty() => Err(io_err(format!("accelerator `{}`: missing key", s))),
+            None => Err(io_err(format!("accelerator `{}`: unknown key `{}`", s, key))),
+        }
+    }
+}
+
+impl fmt::Display for Accelerator {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self.flags & FVIRTKEY != 0 {
+            try!(write!(fmt, "0x{:02X}, {}, VIRTKEY", self.key, self.id));
+        } else {
+            try!(write!(fmt, "{}, {}, ASCII", self.key, self.id));
+        }
+        if self.flags & FNOINVERT != 0 { try!(write!(fmt, ", NOINVERT")); }
+        if self.flags & FCONTROL != 0 { try!(write!(fmt, ", CONTROL")); }
+        if self.flags & FSHIFT != 0 { try!(write!(fmt, ", SHIFT")); }
+        if self.flags & FALT != 0 { try!(write!(fmt, ", ALT")); }
+        Ok(())
+    }
+}
+
+fn io_err(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg)
+}
+
+fn virtual_key(name: &str) -> Option<u16> {
+    let lower = name.to_ascii_lowercase();
+    let bytes = lower.as_bytes();
+    if bytes.len() == 1 && (bytes[0].is_ascii_lowercase() || bytes[0].is_ascii_digit()) {
+        // Letter and digit keys use the upper-case ASCII code.
+        return Some(bytes[0].to_ascii_uppercase() as u16);
+    }
+    if bytes.len() > 1 && bytes[0] == b'f' {
+        if let Ok(n) = lower[1..].parse::<u16>() {
+            return if (1..=24).contains(&n) { Some(0x70 + n - 1) } else { None };
+        }
+    }
+    let vk = match &*lower {
+        "backspace" => 0x08,
+        "tab" => 0x09,
+        "enter" | "return" => 0x0D,
+        "esc" | "escape" => 0x1B,
+        "space" => 0x20,
+        "pageup" | "pgup" => 0x21,
+        "pagedown" | "pgdn" => 0x22,
+        "end" => 0x23,
+        "home" => 0x24,
+        "left" => 0x25,
+        "up" => 0x26,
+        "right" => 0x27,
+        "down" => 0x28,
+        "insert" | "ins" => 0x2D,
+        "delete" | "del" => 0x2E,
+        "plus" => 0xBB,
+        "minus" => 0xBD,
+        _ => return None,
+    };
+    Some(vk)
+}
+
+/// Serializes an accelerator table to the `ACCELTABLEENTRY` layout used by resources.
+pub fn accelerator_table_data(entries: &[Accelerator]) -> Vec<u8> {
+    let mut data = vec![];
+    for (i, accel) in entries.iter().enumerate() {
+        let mut flags = accel.flags as u16;
+        if i + 1 == entries.len() {
+            flags |= ACCEL_LAST;
+        }
+        data.push_u16(flags);
+        data.push_u16(accel.key);
+        data.push_u16(accel.id);
+        data.push_u16(0);
+    }
+    data
+}
+
+#[cfg(test)]
+#[test]
+fn test_parse_accelerator() {
+    fn parse(s: &str) -> (u8, u16) {
+        let accel = Accelerator::parse(s, 7).unwrap();
+        assert_eq!(accel.id, 7);
+        (accel.flags, accel.key)
+    }
+
+    assert_eq!(parse("Ctrl+Shift+S"), (FVIRTKEY | FCONTROL | FSHIFT, 0x53));
+    assert_eq!(parse("control + s"), (FVIRTKEY | FCONTROL, 0x53));
+    assert_eq!(parse("Alt+F4"), (FVIRTKEY | FALT, 0x73));
+    assert_eq!(parse("F24"), (FVIRTKEY, 0x87));
+    assert_eq!(parse("Shift+Del"), (FVIRTKEY | FSHIFT, 0x2E));
+    assert_eq!(parse("Ctrl+Plus"), (FVIRTKEY | FCONTROL, 0xBB));
+    assert_eq!(parse("Ctrl+1"), (FVIRTKEY | FCONTROL, 0x31));
+
+    fn err(s: &str) -> String {
+        Accelerator::parse(s, 7).unwrap_err().to_string()
+    }
+
+    assert_eq!(err("Ctrl+"), "accelerator `Ctrl+`: missing key");
+    assert_eq!(err("Ctrl+F25"), "accelerator `Ctrl+F25`: unknown key `F25`");
+    assert_eq!(err("Hyper+S"), "accelerator `Hyper+S`: unknown modifier `Hyper`");
+    assert_eq!(err("Ctrl++S"), "accelerator `Ctrl++S`: empty modifier");
+    assert_eq!(err("Ctrl+Control+S"), "accelerator `Ctrl+Control+S`: modifier `Control` given twice");
+}
@@ -0,0 +1,38 @@
+/*!
+Helpers for assembling little-endian binary templates.
+*/
+
+pub trait ByteBuf {
+    fn push_u16(&mut self, v: u16);
+    fn push_u32(&mut self, v: u32);
+
+    /// Writes `s` as UTF-16, followed by a terminating zero.
+    fn push_wstr_null(&mut self, s: &str);
+
+    /// Pads with zeroes until the length is a multiple of `align`.
+    fn align_to(&mut self, align: usize);
+}
+
+impl ByteBuf for Vec<u8> {
+    fn push_u16(&mut self, v: u16) {
+        self.push(v as u8);
+        self.push((v >> 8) as u8);
+    }
+
+    fn push_u32(&mut self, v: u32) {
+        self.push_u16(v as u16);
+        self.push_u16((v >> 16) as u16);
+    }
+
+    fn push_wstr_null(&mut self, s: &str) {
+        for w in s.encode_utf16() {
+            self.push_u16(w);
+        }
+        self.push_u16(0);
+    }
+
+    fn align_to(&mut self, align: usize) {
+        let pad = (align - self.len() % align) % align;
+        self.extend((0..pad).map(|_| 0));
+    }
+}
@@ -0,0 +1,159 @@
+/*!
+Dialog box templates.
+
+These are serialized as extended templates (`DLGTEMPLATEEX` followed by one `DLGITEMTEMPLATEEX` per control), which is both what `rc` produces for `DIALOGEX` resources and what `DialogBoxIndirectParamW` and `CreateDialogIndirectParamW` expect.
+*/
+use std::fmt;
+use ::bytes::ByteBuf;
+
+pub const DS_SETFONT: u32 = 0x40;
+
+pub struct DialogTemplate {
+    /// The dialog's title.  Note that the title bar is only shown if `style` includes `WS_CAPTION`.
+    pub caption: String,
+
+    /// The font used by the dialog and its controls.  If present, `DS_SETFONT` is added to the dialog's style automatically.
+    pub font: Option<DialogFont>,
+
+    pub style: u32,
+    pub ex_style: u32,
+
+    /// Position and size of the dialog, in dialog units.
+    pub rect: DialogRect,
+
+    pub controls: Vec<DialogControl>,
+}
+
+impl DialogTemplate {
+    /// The style actually written out, with `DS_SETFONT` matching whether there is a font.
+    pub fn effective_style(&self) -> u32 {
+        match self.font {
+            Some(_) => self.style | DS_SETFONT,
+            None => self.style & !DS_SETFONT,
+        }
+    }
+
+    /// Serializes the template to the `DLGTEMPLATEEX` layout.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![];
+        data.push_u16(1); // dlgVer
+        data.push_u16(0xFFFF); // signature
+        data.push_u32(0); // helpID
+        data.push_u32(self.ex_style);
+        data.push_u32(self.effective_style());
+        data.push_u16(self.controls.len() as u16);
+        push_rect(&mut data, self.rect);
+        data.push_u16(0); // menu
+        data.push_u16(0); // windowClass
+        data.push_wstr_null(&self.caption);
+        if let Some(ref font) = self.font {
+            data.push_u16(font.point_size);
+            data.push_u16(font.weight);
+            data.push(font.italic as u8);
+            data.push(font.charset);
+            data.push_wstr_null(&font.typeface);
+        }
+
+        for control in &self.controls {
+            data.align_to(4);
+            data.push_u32(0); // helpID
+            data.push_u32(control.ex_style);
+            data.push_u32(control.style);
+            push_rect(&mut data, control.rect);
+            data.push_u32(control.id);
+            match control.class.atom() {
+                Some(atom) => {
+                    data.push_u16(0xFFFF);
+                    data.push_u16(atom);
+                },
+                None => data.push_wstr_null(control.class.name()),
+            }
+            data.push_wstr_null(&control.text);
+            data.push_u16(0); // extraCount
+        }
+        data
+    }
+}
+
+pub struct DialogFont {
+    pub point_size: u16,
+    pub weight: u16,
+    pub italic: bool,
+    pub charset: u8,
+    pub typeface: String,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DialogRect {
+    pub x: i16,
+    pub y: i16,
+    pub width: i16,
+    pub height: i16,
+}
+
+impl fmt::Display for DialogRect {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}, {}, {}, {}", self.x, self.y, self.width, self.height)
+    }
+}
+
+pub struct DialogControl {
+    pub class: ControlClass,
+    pub text: String,
+    pub id: u32,
+
+    /// Position and size of the control, in dialog units.
+    pub rect: DialogRect,
+
+    pub style: u32,
+    pub ex_style: u32,
+}
+
+/**
+The window class of a dialog control.
+
+The predefined system classes are stored as atoms; anything else is stored by name.
+*/
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ControlClass {
+    Button,
+    Edit,
+    Static,
+    ListBox,
+    ScrollBar,
+    ComboBox,
+    Named(String),
+}
+
+impl ControlClass {
+    pub fn name(&self) -> &str {
+        match *self {
+            ControlClass::Button => "Button",
+            ControlClass::Edit => "Edit",
+            ControlClass::Static => "Static",
+            ControlClass::ListBox => "ListBox",
+            ControlClass::ScrollBar => "ScrollBar",
+            ControlClass::ComboBox => "ComboBox",
+            ControlClass::Named(ref name) => name,
+        }
+    }
+
+    pub fn atom(&self) -> Option<u16> {
+        match *self {
+            ControlClass::Button => Some(0x0080),
+            ControlClass::Edit => Some(0x0081),
+            ControlClass::Static => Some(0x0082),
+            ControlClass::ListBox => Some(0x0083),
+            ControlClass::ScrollBar => Some(0x0084),
+            ControlClass::ComboBox => Some(0x0085),
+            ControlClass::Named(_) => None,
+        }
+    }
+}
+
+fn push_rect(data: &mut Vec<u8>, rect: DialogRect) {
+    data.push_u16(rect.x as u16);
+    data.push_u16(rect.y as u16);
+    data.push_u16(rect.width as u16);
+    data.push_u16(rect.height as u16);
+}
@@ -0,0 +1,13 @@
+/*!
+Binary templates shared by `wui` and `wui-build`.
+
+Dialogs, menus and accelerators have the same layout whether `wui-build` compiles them into resources or `wui` builds them in memory at runtime, so the models, their parsers and serializers live here, where neither crate has to drag in the other's dependencies.
+*/
+pub use accelerator::*;
+pub use dialog::*;
+pub use menu::*;
+
+mod accelerator;
+mod bytes;
+mod dialog;
+mod menu;
@@ -0,0 +1,115 @@
+/*!
+Menu templates.
+
+These are serialized as extended templates (`MENUEX_TEMPLATE_HEADER` followed by a tree of `MENUEX_TEMPLATE_ITEM`s), which is both what `rc` produces for `MENUEX` resources and what `LoadMenuIndirectW` accepts.
+*/
+use ::bytes::ByteBuf;
+
+pub const MFT_RADIOCHECK: u32 = 0x0200;
+pub const MFT_SEPARATOR: u32 = 0x0800;
+
+pub const MFS_DISABLED: u32 = 0x0003;
+pub const MFS_CHECKED: u32 = 0x0008;
+pub const MFS_DEFAULT: u32 = 0x1000;
+
+const MENUEX_ITEM_POPUP: u16 = 0x01;
+const MENUEX_ITEM_LAST: u16 = 0x80;
+
+pub struct MenuItem {
+    pub text: String,
+
+    /// The command id sent in `WM_COMMAND` when the item is chosen.  Usually zero for sub-menus and separators.
+    pub id: u32,
+
+    /// A combination of the `MFT_*` constants.
+    pub item_type: u32,
+
+    /// A combination of the `MFS_*` constants.
+    pub state: u32,
+
+    /// If present, this item opens a sub-menu containing these items.
+    pub sub_menu: Option<Vec<MenuItem>>,
+}
+
+impl MenuItem {
+    pub fn separator() -> MenuItem {
+        MenuItem {
+            text: String::new(),
+            id: 0,
+            item_type: MFT_SEPARATOR,
+            state: 0,
+            sub_menu: None,
+        }
+    }
+}
+
+/// Serializes a menu to the `MENUEX` template layout.
+pub fn menu_template_data(items: &[MenuItem]) -> Vec<u8> {
+    let mut data = vec![];
+    data.push_u16(1); // wVersion
+    data.push_u16(4); // wOffset, from just after this field to the first item
+    data.push_u32(0); // dwHelpId
+    push_items(&mut data, items);
+    data
+}
+
+fn push_items(data: &mut Vec<u8>, items: &[MenuItem]) {
+    for (i, item) in items.iter().enumerate() {
+        let mut flags = 0;
+        if i + 1 == items.len() {
+            flags |= MENUEX_ITEM_LAST;
+        }
+        if item.sub_menu.is_some() {
+            flags |= MENUEX_ITEM_POPUP;
+        }
+
+        data.push_u32(item.item_type);
+        data.push_u32(item.state);
+        data.push_u32(item.id);
+        data.push_u16(flags);
+        data.push_wstr_null(&item.text);
+        data.align_to(4);
+
+        if let Some(ref sub_menu) = item.sub_menu {
+            data.push_u32(0); // dwHelpId
+            push_items(data, sub_menu);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_menu_template_data() {
+    fn item(text: &str, id: u32, item_type: u32, state: u32) -> MenuItem {
+        MenuItem { text: text.into(), id: id, item_type: item_type, state: state, sub_menu: None }
+    }
+
+    let items = vec![
+        MenuItem {
+            sub_menu: Some(vec![
+                item("O", 100, 0, MFS_DEFAULT),
+                MenuItem::separator(),
+            ]),
+            ..item("F", 0, 0, 0)
+        },
+        item("Hi", 300, MFT_RADIOCHECK, MFS_CHECKED),
+    ];
+
+    let expected: &[u8] = &[
+        // Header.
+        0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // "F": type, state, id, flags (popup), text, pad, help id.
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x01, 0x00, b'F', 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // "O": default.
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x64, 0x00, 0x00, 0x00,
+        0x00, 0x00, b'O', 0x00, 0x00, 0x00, 0x00, 0x00,
+        // Separator, last in its menu.
+        0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x80, 0x00, 0x00, 0x00,
+        // "Hi": radio, checked, last in the menu bar.
+        0x00, 0x02, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x2c, 0x01, 0x00, 0x00,
+        0x80, 0x00, b'H', 0x00, b'i', 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(&menu_template_data(&items)[..], expected);
+}